@@ -0,0 +1,113 @@
+// codec_benchmark.rs
+//
+// What RaptorQ costs the CPU per byte of object, apart from any network:
+// encoding an object into its source symbols plus some repair symbols, and
+// decoding it from what a receiver would be left with after losing as many
+// source symbols as there were repair symbols to replace them.
+//
+// Symbol sizes span the range `calculate_symbol_size` adapts over, objects
+// range from the 8 kB the network benchmarks send to 1 MB, and the repair
+// overhead is the share of repair symbols on top of the source symbols. With
+// no repair symbols the decoder only has to copy the source symbols out, so
+// that case is the floor the others are measured against.
+use criterion::{
+    criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, SamplingMode, Throughput,
+};
+use nyxpsi::{
+    report::size_label,
+    stats::{MAX_SYMBOL_SIZE, MIN_SYMBOL_SIZE},
+};
+use rand::{rngs::StdRng, seq::index, Rng, SeedableRng};
+use raptorq::{Decoder, Encoder, EncodingPacket, ObjectTransmissionInformation};
+use std::time::Duration;
+
+const OBJECT_SIZES: [usize; 3] = [8_000, 64_000, 1_000_000];
+const SYMBOL_STEP: usize = 500;
+/// Repair symbols as a share of the source symbols.
+const REPAIR_OVERHEADS: [f64; 3] = [0.0, 0.1, 0.5];
+
+/// The object's source symbols followed by `overhead` as many repair ones.
+fn encode(data: &[u8], symbol_size: u16, overhead: f64) -> Vec<EncodingPacket> {
+    let oti = ObjectTransmissionInformation::with_defaults(data.len() as u64, symbol_size);
+    Encoder::new(data, oti).get_encoded_packets(repair_symbols(data.len(), symbol_size, overhead))
+}
+
+fn repair_symbols(object_size: usize, symbol_size: u16, overhead: f64) -> u32 {
+    let source_symbols = object_size.div_ceil(symbol_size as usize);
+    (source_symbols as f64 * overhead).ceil() as u32
+}
+
+/// What reaches the receiver of `encode`'s packets when a random source
+/// symbol is lost for every repair symbol: just enough to decode from.
+fn survivors(
+    mut packets: Vec<EncodingPacket>,
+    repair: u32,
+    rng: &mut StdRng,
+) -> Vec<EncodingPacket> {
+    let source_symbols = packets.len() - repair as usize;
+    let mut lost = index::sample(rng, source_symbols, repair as usize).into_vec();
+    lost.sort_unstable();
+    for i in lost.into_iter().rev() {
+        packets.remove(i);
+    }
+    packets
+}
+
+fn decode(oti: ObjectTransmissionInformation, packets: Vec<EncodingPacket>) -> Vec<u8> {
+    let mut decoder = Decoder::new(oti);
+    packets
+        .into_iter()
+        .find_map(|packet| decoder.decode(packet))
+        .expect("too few symbols to decode")
+}
+
+fn run_benchmarks(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    for object_size in OBJECT_SIZES {
+        let data: Vec<u8> = (0..object_size).map(|_| rng.gen()).collect();
+        for (operation, decoding) in [("encode", false), ("decode", true)] {
+            let mut group =
+                c.benchmark_group(format!("{} {}", operation, size_label(object_size as u64)));
+            group.throughput(Throughput::Bytes(object_size as u64));
+            // A 1 MB object takes long enough per iteration that criterion
+            // would otherwise ask for far more time than it is given.
+            group.sampling_mode(SamplingMode::Flat);
+            group.sample_size(10);
+            group.measurement_time(Duration::from_secs(2));
+
+            for symbol_size in (MIN_SYMBOL_SIZE..=MAX_SYMBOL_SIZE).step_by(SYMBOL_STEP) {
+                for overhead in REPAIR_OVERHEADS {
+                    let id = BenchmarkId::new(
+                        format!("{} B symbols", symbol_size),
+                        format!("{:.0}% repair", overhead * 100.0),
+                    );
+                    if !decoding {
+                        group.bench_function(id, |b| {
+                            b.iter(|| encode(&data, symbol_size, overhead))
+                        });
+                        continue;
+                    }
+
+                    let oti = ObjectTransmissionInformation::with_defaults(
+                        object_size as u64,
+                        symbol_size,
+                    );
+                    let repair = repair_symbols(object_size, symbol_size, overhead);
+                    let packets = survivors(encode(&data, symbol_size, overhead), repair, &mut rng);
+                    assert_eq!(decode(oti, packets.clone()), data);
+                    group.bench_function(id, |b| {
+                        b.iter_batched(
+                            || packets.clone(),
+                            |packets| decode(oti, packets),
+                            BatchSize::LargeInput,
+                        )
+                    });
+                }
+            }
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, run_benchmarks);
+criterion_main!(benches);
@@ -1,265 +1,652 @@
-use criterion::BenchmarkId;
-use criterion::{criterion_group, criterion_main, Criterion};
-use rand::Rng;
-use raptorq::{Decoder, Encoder, ObjectTransmissionInformation};
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream, UdpSocket};
-use std::thread;
-use std::time::{Duration, Instant};
-
-const DATA_SIZE: usize = 1_000_000; // 1 MB
-const SYMBOL_SIZE: u16 = 1000;
+// network_benchmark.rs
+//
+// Transfers of a payload between real endpoints on loopback, every protocol
+// crossing the same impaired link: nyx-ψ over UDP and UDP-Lite, and plain
+// UDP, go through the impairment proxy, and TCP goes through a relay that
+// applies the same link to its segments. Each transfer is timed until the
+// receiver has the whole payload (or gives up), and reports its goodput and
+// how many bytes crossed the link for the payload.
+//
+// The proxy cannot carry TCP, and leaving data out of a TCP stream would not
+// be TCP, so the relay models what the kernel would do about a lost segment:
+// resend it a round trip later, as fast retransmit would, then on a
+// retransmission timer that doubles each time, holding back everything
+// behind it meanwhile. ACKs and congestion control are not modelled, which
+// if anything flatters TCP. Since this is a model of TCP rather than the
+// kernel's TCP on an impaired path, its results are labelled as modelled.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, SamplingMode};
+use nyxpsi::{
+    proxy::{Proxy, ProxyConfig},
+    report::{size_label, BenchRecord, BenchResults},
+    transport::{self, LinkConfig, LossModel, LossProcess, Trace, TracePlayer, TransportKind},
+    NyxError, Receiver, ReceiverConfig, Sender, SenderConfig,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    error::Error,
+    fmt, fs, io,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc,
+    time::{sleep_until, timeout, timeout_at, Instant},
+};
+
+const PAYLOAD_SIZES: [usize; 2] = [64_000, 1_000_000];
+/// nyx-ψ sends the payload as objects of this size, one after another,
+/// each resent until the receiver acknowledges it.
+const OBJECT_SIZE: usize = 8_000;
+/// What plain UDP sends the payload in.
+const DATAGRAM_SIZE: usize = 1024;
+/// What plain UDP sends at, in bits per second, if the link sets no rate.
+/// It has no congestion control, and sent flat out it would overflow the
+/// proxy's socket buffer before the link lost anything.
+const UDP_RATE: u64 = 100_000_000;
+/// What TCP sends the payload in: the MSS of an Ethernet path.
+const SEGMENT_SIZE: usize = 1460;
 const LATENCY_MS: u64 = 1; // 1ms latency
+/// Linux's minimum TCP retransmission timeout, which nyx-ψ also waits for
+/// feedback before resending, so that neither recovers sooner by
+/// configuration alone.
+const RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long plain UDP's receiver waits for more before deciding the
+/// transfer is over, as nothing tells it.
+const QUIET_TIME: Duration = Duration::from_millis(250);
 const BENCHMARK_TIMEOUT: Duration = Duration::from_secs(5);
 
+type BoxResult<T> = Result<T, Box<dyn Error>>;
+
 #[derive(Debug)]
 struct BenchmarkResult {
+    /// Until the receiver had the whole payload, or gave up.
     duration: Duration,
-    packets_sent: usize,
-    packets_received: usize,
+    /// Bytes of the payload the receiver got.
+    delivered: usize,
+    /// Bytes sent across the link either way during the transfer.
+    link_bytes: u64,
     transfer_success: bool,
 }
 
-fn simulate_network_latency() {
-    thread::sleep(Duration::from_millis(LATENCY_MS));
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    Nyxpsi(TransportKind),
+    Tcp,
+    Udp,
 }
 
-fn simulate_packet_loss(loss_rate: f64) -> bool {
-    rand::thread_rng().gen::<f64>() > loss_rate
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Nyxpsi(kind) => write!(f, "nyxpsi ({})", kind),
+            // Not measured like the others; see the top of the file.
+            Protocol::Tcp => f.write_str("TCP (modelled)"),
+            Protocol::Udp => f.write_str("UDP"),
+        }
+    }
 }
 
-fn generate_random_data() -> Vec<u8> {
-    let mut data = vec![0u8; DATA_SIZE];
-    rand::thread_rng().fill(&mut data[..]);
-    data
+fn localhost() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 0))
 }
 
-fn benchmark_raptorq(loss_rate: f64) -> BenchmarkResult {
-    let data = generate_random_data();
-    let start = Instant::now();
-
-    let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE as u64, SYMBOL_SIZE);
-    let encoder = Encoder::new(&data, oti);
-    let mut decoder = Decoder::new(oti);
-
-    let mut packets_sent = 0;
-    let mut packets_received = 0;
-    let mut decoded_data: Option<Vec<u8>> = None;
-
-    println!("Starting RaptorQ benchmark with loss rate: {}", loss_rate);
+/// The link back from the receiver: the same conditions, but not the same
+/// random choices.
+fn reverse(link: &LinkConfig) -> LinkConfig {
+    LinkConfig {
+        seed: link.seed.wrapping_add(1 << 32),
+        ..link.clone()
+    }
+}
 
-    // Calculate the number of packets needed (with some redundancy)
-    let packets_needed = (DATA_SIZE / SYMBOL_SIZE as usize) as u32;
-    let total_packets = (packets_needed as f64 * (1.0 + loss_rate) * 1.1) as u32; // Add 10% extra
+fn generate_random_data(size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    rand::thread_rng().fill(&mut data[..]);
+    data
+}
 
-    for packet in encoder.get_encoded_packets(total_packets) {
-        packets_sent += 1;
+/// A proxy in front of `upstream`, forwarding over `kind` across `link`.
+fn start_proxy(
+    kind: TransportKind,
+    upstream: SocketAddr,
+    link: &LinkConfig,
+) -> io::Result<Arc<Proxy>> {
+    let proxy = Arc::new(Proxy::bind(ProxyConfig {
+        transports: vec![kind],
+        listen: localhost(),
+        upstream,
+        up: link.clone(),
+        down: reverse(link),
+        ..ProxyConfig::default()
+    })?);
+    tokio::spawn({
+        let proxy = proxy.clone();
+        async move { proxy.run().await }
+    });
+    Ok(proxy)
+}
 
-        if simulate_packet_loss(loss_rate) {
-            simulate_network_latency();
-            packets_received += 1;
+fn link_bytes(proxy: &Proxy) -> u64 {
+    let stats = proxy.stats();
+    stats.up.bytes + stats.down.bytes
+}
 
-            if let Some(data) = decoder.decode(packet) {
-                decoded_data = Some(data);
-                println!("Successfully decoded data after {} packets", packets_sent);
+async fn benchmark_nyxpsi(
+    kind: TransportKind,
+    link: &LinkConfig,
+    data: &[u8],
+) -> BoxResult<BenchmarkResult> {
+    let mut receiver = Receiver::bind_with_config(
+        localhost(),
+        ReceiverConfig {
+            transports: vec![kind],
+            ..ReceiverConfig::default()
+        },
+    )
+    .await?;
+    // Connecting and discovering the path MTU happen over a perfect link
+    // and are left out of the time, as TCP's handshake is.
+    let perfect = LinkConfig {
+        seed: link.seed,
+        ..LinkConfig::default()
+    };
+    let proxy = start_proxy(kind, receiver.local_addr()?, &perfect)?;
+    // The receiver keeps answering after the last object, in case its
+    // feedback is lost and the object is resent.
+    let (objects, mut decoded) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok(object) = receiver.recv_object().await {
+            if objects.send(object.data).is_err() {
                 break;
             }
         }
+    });
 
-        if packets_sent % 1000 == 0 || packets_sent == total_packets as usize {
-            println!(
-                "Sent {} packets, received {}",
-                packets_sent, packets_received
-            );
-        }
+    let config = SenderConfig {
+        transports: vec![kind],
+        timeout: RETRANSMISSION_TIMEOUT,
+        ..SenderConfig::default()
+    };
+    let mut sender = Sender::connect_with_config(proxy.local_addr(), config).await?;
+    sender.discover_path_mtu().await?;
+    proxy.set_links(link.clone(), reverse(link));
 
-        if start.elapsed() >= BENCHMARK_TIMEOUT {
-            println!("Benchmark timed out");
-            break;
+    let bytes_before = link_bytes(&proxy);
+    let start = Instant::now();
+    let deadline = start + BENCHMARK_TIMEOUT;
+    let sending = async {
+        for object in data.chunks(OBJECT_SIZE) {
+            // Like the client, resend until the receiver acknowledges.
+            loop {
+                match sender.send_object(object).await {
+                    Ok(_) => break,
+                    Err(NyxError::Timeout) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
         }
+        Ok(())
+    };
+    let mut received = Vec::new();
+    let receiving = async {
+        let mut last = None;
+        while received.len() < data.len() {
+            let Ok(Some(object)) = timeout_at(deadline, decoded.recv()).await else {
+                break;
+            };
+            // An object resent after its feedback was lost is decoded again.
+            if last.as_ref() != Some(&object) {
+                received.extend_from_slice(&object);
+                last = Some(object);
+            }
+        }
+    };
+    let (sent, ()) = tokio::join!(timeout_at(deadline, sending), receiving);
+    if let Ok(sent) = sent {
+        sent?;
     }
 
-    let duration = start.elapsed();
-    let success = decoded_data.as_ref().map_or(false, |d| d == &data);
-    println!(
-        "RaptorQ benchmark completed in {:?}, success: {}",
-        duration, success
-    );
-
-    BenchmarkResult {
-        duration,
-        packets_sent,
-        packets_received,
-        transfer_success: success,
-    }
+    Ok(BenchmarkResult {
+        duration: start.elapsed(),
+        delivered: received.len(),
+        link_bytes: link_bytes(&proxy) - bytes_before,
+        transfer_success: received == data,
+    })
 }
 
-fn benchmark_tcp(loss_rate: f64) -> BenchmarkResult {
-    let data = generate_random_data();
-    let start = Instant::now();
+async fn benchmark_udp(link: &LinkConfig, data: &[u8]) -> BoxResult<BenchmarkResult> {
+    let receiver = UdpSocket::bind(localhost()).await?;
+    let proxy = start_proxy(TransportKind::Udp, receiver.local_addr()?, link)?;
+    let sender = UdpSocket::bind(localhost()).await?;
 
-    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-    let addr = listener.local_addr().unwrap();
-
-    let data_clone = data.clone();
-    let sender = thread::spawn(move || {
-        let mut stream = TcpStream::connect(addr).unwrap();
-        let mut packets_sent = 0;
-        for chunk in data_clone.chunks(1024) {
-            packets_sent += 1;
-            if simulate_packet_loss(loss_rate) {
-                simulate_network_latency();
-                stream.write_all(chunk).unwrap();
+    let start = Instant::now();
+    let deadline = start + BENCHMARK_TIMEOUT;
+    let gap = Duration::from_secs_f64(
+        DATAGRAM_SIZE as f64 * 8.0 / link.bandwidth.unwrap_or(UDP_RATE).max(1) as f64,
+    );
+    let sending = async {
+        for (i, chunk) in data.chunks(DATAGRAM_SIZE).enumerate() {
+            let due = start + gap * i as u32;
+            if due > Instant::now() {
+                sleep_until(due).await;
             }
+            sender.send_to(chunk, proxy.local_addr()).await?;
         }
-        packets_sent
-    });
-
-    let receiver = thread::spawn(move || {
-        let (mut stream, _) = listener.accept().unwrap();
-        let mut received_data = Vec::new();
-        let mut packets_received = 0;
-        let mut buf = [0u8; 1024];
-        while let Ok(n) = stream.read(&mut buf) {
-            if n == 0 {
-                break;
+        io::Result::Ok(())
+    };
+    let mut received = Vec::new();
+    let receiving = async {
+        let mut buf = [0u8; DATAGRAM_SIZE];
+        while received.len() < data.len() && Instant::now() < deadline {
+            match timeout(QUIET_TIME, receiver.recv_from(&mut buf)).await {
+                Ok(Ok((len, _))) => received.extend_from_slice(&buf[..len]),
+                _ => break,
             }
-            packets_received += 1;
-            received_data.extend_from_slice(&buf[..n]);
         }
-        (received_data, packets_received)
-    });
+    };
+    let (sent, ()) = tokio::join!(sending, receiving);
+    sent?;
+
+    Ok(BenchmarkResult {
+        duration: start.elapsed(),
+        delivered: received.len().min(data.len()),
+        link_bytes: link_bytes(&proxy),
+        transfer_success: received == data,
+    })
+}
 
-    let packets_sent = sender.join().unwrap();
-    let (received_data, packets_received) = receiver.join().unwrap();
-    let duration = start.elapsed();
+async fn benchmark_tcp(link: &LinkConfig, data: &[u8]) -> BoxResult<BenchmarkResult> {
+    let server = TcpListener::bind(localhost()).await?;
+    let relay = TcpListener::bind(localhost()).await?;
+    let relay_addr = relay.local_addr()?;
+    let stream_link = Arc::new(Mutex::new(StreamLink::new(link.clone())));
+    tokio::spawn(relay_stream(
+        relay,
+        server.local_addr()?,
+        stream_link.clone(),
+    ));
+    let mut stream = TcpStream::connect(relay_addr).await?;
+    stream.set_nodelay(true)?;
+    let (mut inbound, _) = server.accept().await?;
 
-    BenchmarkResult {
-        duration,
-        packets_sent,
-        packets_received,
-        transfer_success: received_data == data,
-    }
+    let start = Instant::now();
+    let deadline = start + BENCHMARK_TIMEOUT;
+    let sending = async {
+        stream.write_all(data).await?;
+        stream.shutdown().await
+    };
+    let mut received = Vec::new();
+    let receiving = async {
+        let mut buf = vec![0u8; 64 * 1024];
+        while received.len() < data.len() {
+            match timeout_at(deadline, inbound.read(&mut buf)).await {
+                Ok(Ok(len)) if len > 0 => received.extend_from_slice(&buf[..len]),
+                _ => break,
+            }
+        }
+    };
+    let (sent, ()) = tokio::join!(sending, receiving);
+    sent?;
+
+    let link_bytes = stream_link.lock().unwrap().bytes;
+    Ok(BenchmarkResult {
+        duration: start.elapsed(),
+        delivered: received.len(),
+        link_bytes,
+        transfer_success: received == data,
+    })
 }
 
-fn benchmark_udp(loss_rate: f64) -> BenchmarkResult {
-    let data = generate_random_data();
-    let start = Instant::now();
+/// Accepts one connection and relays what the client writes on to
+/// `server`, each segment arriving when `link` says it would.
+async fn relay_stream(
+    listener: TcpListener,
+    server: SocketAddr,
+    link: Arc<Mutex<StreamLink>>,
+) -> io::Result<()> {
+    let (mut client, _) = listener.accept().await?;
+    let mut upstream = TcpStream::connect(server).await?;
+    upstream.set_nodelay(true)?;
+    let (segments, mut arriving) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+    let reading = async move {
+        let mut buf = [0u8; SEGMENT_SIZE];
+        loop {
+            let len = client.read(&mut buf).await?;
+            if len == 0 {
+                return Ok(());
+            }
+            let arrival = link.lock().unwrap().send(len);
+            // The writing half only goes away on an error of its own.
+            let _ = segments.send((arrival, buf[..len].to_vec()));
+        }
+    };
+    let writing = async move {
+        while let Some((arrival, segment)) = arriving.recv().await {
+            sleep_until(arrival).await;
+            upstream.write_all(&segment).await?;
+        }
+        upstream.shutdown().await
+    };
+    tokio::try_join!(reading, writing)?;
+    Ok(())
+}
 
-    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
-    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
-    let recv_addr = receiver.local_addr().unwrap();
+/// `p` as a probability `gen_bool` accepts, as `SimulatedTransport` takes
+/// it: clamped to [0, 1], or 0 if it is not a number.
+fn chance(p: f64) -> f64 {
+    if p.is_finite() {
+        p.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
 
-    receiver
-        .set_read_timeout(Some(Duration::from_secs(1)))
-        .unwrap();
+/// TCP's view of a [`LinkConfig`]: when each segment gets through, resends
+/// included.
+struct StreamLink {
+    config: LinkConfig,
+    loss: LossProcess,
+    trace: Option<TracePlayer>,
+    started: Instant,
+    rng: StdRng,
+    /// When the link finishes sending what is already queued on it.
+    busy_until: Instant,
+    /// When the last segment arrived; those after it are held back until
+    /// then.
+    delivered_until: Instant,
+    rtt: Duration,
+    /// Bytes of the segments sent, resends included.
+    bytes: u64,
+}
 
-    let data_clone = data.clone();
-    let sender_thread = thread::spawn(move || {
-        let mut packets_sent = 0;
-        for chunk in data_clone.chunks(1024) {
-            packets_sent += 1;
-            if simulate_packet_loss(loss_rate) {
-                simulate_network_latency();
-                sender.send_to(chunk, recv_addr).unwrap();
-            }
+impl StreamLink {
+    fn new(config: LinkConfig) -> Self {
+        StreamLink {
+            loss: LossProcess::new(config.loss.clone()),
+            trace: config
+                .trace
+                .clone()
+                .map(|trace| TracePlayer::new(trace, config.replay)),
+            started: Instant::now(),
+            rng: StdRng::seed_from_u64(config.seed),
+            busy_until: Instant::now(),
+            delivered_until: Instant::now(),
+            rtt: config.delay * 2,
+            bytes: 0,
+            config,
         }
-        packets_sent
-    });
+    }
 
-    let receiver_thread = thread::spawn(move || {
-        let mut buf = [0u8; 1024];
-        let mut received_data = Vec::new();
-        let mut packets_received = 0;
-        let timeout = Instant::now() + Duration::from_secs(5); // 5 second overall timeout
-
-        while received_data.len() < DATA_SIZE && Instant::now() < timeout {
-            match receiver.recv_from(&mut buf) {
-                Ok((size, _)) => {
-                    packets_received += 1;
-                    received_data.extend_from_slice(&buf[..size]);
+    /// When a segment of `len` bytes written now is delivered.
+    fn send(&mut self, len: usize) -> Instant {
+        let mut sent_at = Instant::now();
+        let mut resends = 0;
+        loop {
+            let start = self.busy_until.max(sent_at);
+            let departure = match self.config.bandwidth {
+                Some(bandwidth) => {
+                    start + Duration::from_secs_f64(len as f64 * 8.0 / bandwidth.max(1) as f64)
+                }
+                None => start,
+            };
+            self.busy_until = departure;
+            self.bytes += len as u64;
+            let (lost, delay) = match &mut self.trace {
+                Some(trace) => {
+                    let record = trace.next(sent_at.saturating_duration_since(self.started));
+                    (!record.delivered, record.delay)
+                }
+                None => (self.loss.lose(&mut self.rng), self.config.delay),
+            };
+            // A corrupted segment fails its checksum, so it is lost too.
+            let corrupted = self.rng.gen_bool(chance(self.config.corrupt));
+            if !lost && !corrupted {
+                if self.rng.gen_bool(chance(self.config.duplicate)) {
+                    self.bytes += len as u64;
                 }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // Timeout occurred, continue the loop
-                    continue;
+                let mut arrival = departure + delay + self.config.jitter.mul_f64(self.rng.gen());
+                if self.rng.gen_bool(chance(self.config.reorder)) {
+                    arrival += self.config.reorder_delay;
                 }
-                Err(_) => break,
+                self.rtt = delay * 2;
+                self.delivered_until = self.delivered_until.max(arrival);
+                return self.delivered_until;
             }
+            // The first loss shows in duplicate ACKs a round trip later; a
+            // lost resend waits out the retransmission timer.
+            sent_at = departure
+                + match resends {
+                    0 => self.rtt,
+                    n => RETRANSMISSION_TIMEOUT * 2u32.pow((n - 1).min(8)),
+                };
+            resends += 1;
+        }
+    }
+}
+
+/// Runs one transfer of `data` across `link`.
+fn benchmark(protocol: Protocol, link: &LinkConfig, data: &[u8]) -> BenchmarkResult {
+    // A runtime per transfer, so that the tasks and sockets of one are gone
+    // before the next starts.
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let result = runtime.block_on(async {
+        match protocol {
+            Protocol::Nyxpsi(kind) => benchmark_nyxpsi(kind, link, data).await,
+            Protocol::Tcp => benchmark_tcp(link, data).await,
+            Protocol::Udp => benchmark_udp(link, data).await,
         }
-        (received_data, packets_received)
     });
+    result.unwrap_or_else(|e| panic!("{} benchmark failed to run: {}", protocol, e))
+}
 
-    let packets_sent = sender_thread.join().unwrap();
-    let (received_data, packets_received) = receiver_thread.join().unwrap();
-    let duration = start.elapsed();
+/// What the transfers of a benchmark came to.
+#[derive(Clone, Copy, Debug, Default)]
+struct Tally {
+    runs: u64,
+    duration: Duration,
+    delivered: u64,
+    link_bytes: u64,
+    successes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, result: &BenchmarkResult) {
+        self.runs += 1;
+        self.duration += result.duration;
+        self.delivered += result.delivered as u64;
+        self.link_bytes += result.link_bytes;
+        self.successes += result.transfer_success as u64;
+    }
+
+    fn merge(&mut self, other: &Tally) {
+        self.runs += other.runs;
+        self.duration += other.duration;
+        self.delivered += other.delivered;
+        self.link_bytes += other.link_bytes;
+        self.successes += other.successes;
+    }
 
-    BenchmarkResult {
-        duration,
-        packets_sent,
-        packets_received,
-        transfer_success: received_data == data,
+    fn completion_time(&self) -> Duration {
+        self.duration / self.runs.max(1) as u32
+    }
+
+    /// In Mbit/s.
+    fn goodput(&self) -> f64 {
+        self.delivered as f64 * 8.0 / self.duration.as_secs_f64().max(f64::EPSILON) / 1e6
+    }
+
+    /// Bytes on the link for each byte of the payload that got through,
+    /// less one.
+    fn overhead(&self) -> Option<f64> {
+        (self.delivered > 0).then(|| self.link_bytes as f64 / self.delivered as f64 - 1.0)
+    }
+
+    fn success_rate(&self) -> f64 {
+        self.successes as f64 / self.runs.max(1) as f64
+    }
+
+    fn record(
+        &self,
+        protocol: Protocol,
+        scenario: &str,
+        link: &LinkConfig,
+        size: usize,
+    ) -> BenchRecord {
+        BenchRecord {
+            protocol: protocol.to_string(),
+            scenario: scenario.to_string(),
+            loss_rate: link
+                .trace
+                .as_ref()
+                .map_or_else(|| link.loss.loss_rate(), Trace::loss_rate),
+            payload_size: size as u64,
+            runs: self.runs,
+            completion_time: self.completion_time(),
+            goodput: self.goodput(),
+            overhead: self.overhead(),
+            success_rate: self.success_rate(),
+        }
     }
 }
 
+/// Writes `results` as JSON and CSV into the directory named by
+/// NYXPSI_BENCH_RESULTS, `target/nyxpsi-bench` by default, for
+/// `nyxpsi-bench-report` to render.
+fn save_results(results: &BenchResults) -> io::Result<()> {
+    let directory = PathBuf::from(
+        std::env::var("NYXPSI_BENCH_RESULTS").unwrap_or_else(|_| "target/nyxpsi-bench".into()),
+    );
+    fs::create_dir_all(&directory)?;
+    results.save(directory.join("results.json"))?;
+    results.save(directory.join("results.csv"))?;
+    println!("Results written to {}", directory.display());
+    Ok(())
+}
+
 fn run_benchmarks(c: &mut Criterion) {
-    let loss_rates = [0.0, 0.1, 0.5, 0.9];
-    let protocols = ["RaptorQ", "TCP", "UDP"];
+    // The link every transfer crosses, in the proxy's `name=value` settings
+    // from NYXPSI_BENCH_LINK. Each scenario sets its own loss.
+    let link: LinkConfig = std::env::var("NYXPSI_BENCH_LINK")
+        .unwrap_or_else(|_| format!("delay={}", LATENCY_MS))
+        .parse()
+        .unwrap();
+    // Independent loss, then the same average loss arriving in bursts.
+    let mut scenarios = vec![
+        ("0% loss".to_string(), LossModel::Bernoulli(0.0)),
+        ("10% loss".to_string(), LossModel::Bernoulli(0.1)),
+        ("50% loss".to_string(), LossModel::Bernoulli(0.5)),
+        ("90% loss".to_string(), LossModel::Bernoulli(0.9)),
+        (
+            "10% loss in bursts of 10".to_string(),
+            LossModel::bursty(0.1, 10.0),
+        ),
+        (
+            "50% loss in bursts of 10".to_string(),
+            LossModel::bursty(0.5, 10.0),
+        ),
+    ]
+    .into_iter()
+    .map(|(label, loss)| {
+        (
+            label,
+            LinkConfig {
+                loss,
+                ..link.clone()
+            },
+        )
+    })
+    .collect::<Vec<_>>();
+    // Traces of real links, named by NYXPSI_BENCH_TRACES as a
+    // comma-separated list of CSV or JSON files.
+    if let Ok(paths) = std::env::var("NYXPSI_BENCH_TRACES") {
+        for path in paths.split(',').filter(|path| !path.is_empty()) {
+            let trace = Trace::load(path).unwrap();
+            scenarios.push((
+                format!("trace {}", path),
+                LinkConfig {
+                    trace: Some(trace),
+                    ..link.clone()
+                },
+            ));
+        }
+    }
+    let mut protocols = vec![Protocol::Nyxpsi(TransportKind::Udp)];
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    if runtime.block_on(async { transport::bind(TransportKind::UdpLite, localhost()).is_ok() }) {
+        protocols.push(Protocol::Nyxpsi(TransportKind::UdpLite));
+    } else {
+        println!("UDP-Lite is not available; leaving nyxpsi over UDP-Lite out");
+    }
+    protocols.extend([Protocol::Tcp, Protocol::Udp]);
 
     let mut group = c.benchmark_group("Network Protocols");
+    // Transfers take long enough that every sample is one iteration.
+    group.sampling_mode(SamplingMode::Flat);
     group.sample_size(10); // 10 samples
-    group.measurement_time(Duration::from_secs(60)); // Increase to 60 seconds for more stable results
-    group.warm_up_time(Duration::from_secs(5)); // Add warm-up time
-
-    for &loss_rate in &loss_rates {
-        for &protocol in &protocols {
-            group.bench_with_input(
-                BenchmarkId::new(protocol, format!("{:.0}% loss", loss_rate * 100.0)),
-                &loss_rate,
-                |b, &loss_rate| {
-                    b.iter_custom(|iters| {
-                        let mut total_duration = Duration::ZERO;
-                        let mut total_packets_sent = 0u64;
-                        let mut total_packets_received = 0u64;
-                        let mut successes = 0u64;
-
-                        for _ in 0..iters {
-                            let result = match protocol {
-                                "RaptorQ" => benchmark_raptorq(loss_rate),
-                                "TCP" => benchmark_tcp(loss_rate),
-                                "UDP" => benchmark_udp(loss_rate),
-                                _ => unreachable!(),
-                            };
-                            total_duration += result.duration;
-                            total_packets_sent += result.packets_sent as u64;
-                            total_packets_received += result.packets_received as u64;
-                            if result.transfer_success {
-                                successes += 1;
+    group.measurement_time(Duration::from_secs(10));
+    group.warm_up_time(Duration::from_secs(1));
+
+    let mut results = BenchResults::default();
+    for size in PAYLOAD_SIZES {
+        for (label, link) in &scenarios {
+            for &protocol in &protocols {
+                let parameter = format!("{}, {}", size_label(size as u64), label);
+                // Every transfer criterion runs, warm-up included. Each sees
+                // different random choices.
+                let mut total = Tally::default();
+                group.bench_with_input(
+                    BenchmarkId::new(protocol.to_string(), &parameter),
+                    link,
+                    |b, link| {
+                        b.iter_custom(|iters| {
+                            let mut tally = Tally::default();
+                            for _ in 0..iters {
+                                let data = generate_random_data(size);
+                                let link = LinkConfig {
+                                    seed: link.seed.wrapping_add(total.runs + tally.runs),
+                                    ..link.clone()
+                                };
+                                tally.add(&benchmark(protocol, &link, &data));
                             }
-                        }
-
-                        println!(
-                            "Protocol: {}, Loss Rate: {:.1}, Iterations: {}",
-                            protocol, loss_rate, iters
-                        );
-                        println!("Average Duration: {:?}", total_duration / iters as u32);
-                        println!("Average Packets Sent: {}", total_packets_sent / iters);
-                        println!(
-                            "Average Packets Received: {}",
-                            total_packets_received / iters
-                        );
-                        println!(
-                            "Success Rate: {:.2}%",
-                            (successes as f64 / iters as f64) * 100.0
-                        );
-
-                        total_duration
-                    })
-                },
-            );
+                            total.merge(&tally);
+
+                            println!(
+                                "Protocol: {}, Scenario: {}, Iterations: {}",
+                                protocol, parameter, iters
+                            );
+                            println!("Average Completion Time: {:?}", tally.completion_time());
+                            println!("Goodput: {:.2} Mbit/s", tally.goodput());
+                            match tally.overhead() {
+                                Some(overhead) => println!(
+                                    "Overhead: {:.1}% ({} bytes on the link for {} delivered)",
+                                    overhead * 100.0,
+                                    tally.link_bytes / iters,
+                                    tally.delivered / iters
+                                ),
+                                None => println!("Overhead: n/a (nothing delivered)"),
+                            }
+                            println!("Success Rate: {:.2}%", tally.success_rate() * 100.0);
+
+                            tally.duration
+                        })
+                    },
+                );
+                // Benchmarks the command-line filter skipped never ran.
+                if total.runs > 0 {
+                    results
+                        .records
+                        .push(total.record(protocol, label, link, size));
+                }
+            }
         }
     }
 
     group.finish();
+    save_results(&results).unwrap();
 }
 
 criterion_group!(benches, run_benchmarks);
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nyxpsi::packet::Packet;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = Packet::parse(data) {
+        let serialized = packet.serialize();
+        assert_eq!(Packet::parse(&serialized).unwrap(), packet);
+    }
+});
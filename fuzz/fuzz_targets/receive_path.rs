@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nyxpsi::{stats::MAX_JUMBO_SYMBOL_SIZE, Reassembler};
+use std::net::SocketAddr;
+
+// Input is a sequence of datagrams, each prefixed with its length as a
+// big-endian u16, so one run can drive a decoder through a whole object.
+fuzz_target!(|data: &[u8]| {
+    let peer: SocketAddr = "127.0.0.1:55555".parse().unwrap();
+    let mut reassembler = Reassembler::with_max_symbol_size(MAX_JUMBO_SYMBOL_SIZE);
+    let mut rest = data;
+    while rest.len() >= 2 {
+        let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let datagram = &rest[2..(2 + len).min(rest.len())];
+        rest = &rest[2 + datagram.len()..];
+        reassembler.handle_datagram(peer, datagram);
+    }
+});
@@ -0,0 +1,92 @@
+// generate_corpus.rs
+//
+// Records the datagrams of a real Sender/Receiver exchange on loopback and
+// writes them out as seeds for the fuzz targets:
+//
+//     cargo run --manifest-path fuzz/Cargo.toml --bin generate_corpus
+use nyxpsi::{
+    transport::{DatagramTransport, UdpLiteTransport},
+    Receiver, Sender,
+};
+use rand::{thread_rng, Rng};
+use std::{
+    error::Error,
+    fs,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+const OBJECT_SIZES: [usize; 4] = [1, 300, 1300, 4000];
+const OBJECTS_PER_SIZE: usize = 3;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut receiver = Receiver::bind("127.0.0.1:0".parse()?).await?;
+    let receiver_addr = receiver.local_addr()?;
+    tokio::spawn(async move { while receiver.recv_object().await.is_ok() {} });
+
+    // Sits between client and server and records everything it forwards.
+    let tap = UdpLiteTransport::bind("127.0.0.1:0".parse()?)?;
+    let tap_addr = tap.local_addr()?;
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let recorder = captured.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 65535];
+        let mut client: Option<SocketAddr> = None;
+        while let Ok((size, src_addr)) = tap.recv_from(&mut buf).await {
+            let target = if src_addr == receiver_addr {
+                match client {
+                    Some(client) => client,
+                    None => continue,
+                }
+            } else {
+                client = Some(src_addr);
+                receiver_addr
+            };
+            recorder.lock().unwrap().push(buf[..size].to_vec());
+            let _ = tap.send_to(&buf[..size], target).await;
+        }
+    });
+
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    let packet_dir = corpus.join("packet");
+    let receive_path_dir = corpus.join("receive_path");
+    fs::create_dir_all(&packet_dir)?;
+    fs::create_dir_all(&receive_path_dir)?;
+
+    let mut sender = Sender::connect(tap_addr).await?;
+    let mut datagrams = 0;
+    let mut exchanges = 0;
+    for &size in &OBJECT_SIZES {
+        for _ in 0..OBJECTS_PER_SIZE {
+            let mut data = vec![0u8; size];
+            thread_rng().fill(&mut data[..]);
+            if let Err(e) = sender.send_object(&data).await {
+                println!("Object of {} bytes not delivered: {}", size, e);
+            }
+
+            let exchange: Vec<Vec<u8>> = captured.lock().unwrap().drain(..).collect();
+            let mut framed = Vec::new();
+            for datagram in &exchange {
+                fs::write(packet_dir.join(format!("seed-{:04}", datagrams)), datagram)?;
+                datagrams += 1;
+                framed.extend_from_slice(&(datagram.len() as u16).to_be_bytes());
+                framed.extend_from_slice(datagram);
+            }
+            fs::write(
+                receive_path_dir.join(format!("seed-{:04}", exchanges)),
+                framed,
+            )?;
+            exchanges += 1;
+        }
+    }
+
+    println!(
+        "Wrote {} packet seeds and {} receive_path seeds to {}",
+        datagrams,
+        exchanges,
+        corpus.display()
+    );
+    Ok(())
+}
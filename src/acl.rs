@@ -0,0 +1,255 @@
+// acl.rs
+//
+// Which senders a receiver accepts datagrams from: by source address, and
+// with authentication on, by the identity a session authenticated as. An
+// access list is written one rule per line:
+//
+//     # Comments and blank lines are ignored.
+//     allow 10.0.0.0/8
+//     deny 10.0.0.66
+//     allow key 6a1f...            (a sender's Noise public key, 64 hex digits)
+//     deny psk guests              (the name of one of the receiver's PSKs)
+//     limit key 6a1f... max_sessions=2 max_packets_per_second=500
+//
+// Denials win over allowances. An address must match an `allow` line if
+// there are any, and likewise an identity must match an `allow key` or
+// `allow psk` line if there are any.
+use crate::limits::Limits;
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+/// A block of addresses, written as an address and prefix length. A bare
+/// address is a block of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("not an IP address: {}", addr))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|&prefix| prefix <= max)
+                .ok_or_else(|| format!("not a prefix length for {}: {}", addr, prefix))?,
+            None => max,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let (bytes, bits) = (prefix as usize / 8, prefix % 8);
+    net[..bytes] == ip[..bytes]
+        && (bits == 0 || (net[bytes] ^ ip[bytes]) & (0xff << (8 - bits)) == 0)
+}
+
+/// What an authenticated session proves about its sender.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Identity {
+    /// The static public key a sender completed a Noise handshake with.
+    Key([u8; 32]),
+    /// The name of the pre-shared key a sender's datagrams were sealed
+    /// with.
+    Psk(String),
+}
+
+impl FromStr for Identity {
+    type Err = String;
+
+    /// Parses `key` followed by a public key in hex, or `psk` followed by
+    /// a name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(char::is_whitespace) {
+            Some(("key", key)) => parse_key(key).map(Identity::Key),
+            Some(("psk", name)) => Ok(Identity::Psk(name.trim().to_string())),
+            _ => Err(format!("not an identity: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Identity::Key(key) => {
+                write!(f, "key ")?;
+                key.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Identity::Psk(name) => write!(f, "psk {}", name),
+        }
+    }
+}
+
+/// Address and identity rules, and limits for particular identities. An
+/// identity's `max_sessions` and `max_decoder_memory` bound all its sessions
+/// together, its other limits each of them, within the receiver's own
+/// overall limits. The default access list accepts everyone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+    pub allow_identities: Vec<Identity>,
+    pub deny_identities: Vec<Identity>,
+    pub limits: Vec<(Identity, Limits)>,
+}
+
+impl Acl {
+    pub fn permits_address(&self, peer: SocketAddr) -> bool {
+        let ip = peer.ip();
+        !self.deny.iter().any(|cidr| cidr.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip)))
+    }
+
+    /// Whether a session authenticated as `identity` may go on. `None`
+    /// stands for a session sealed with the receiver's unnamed PSK, which
+    /// proves nothing about who sent it and so is refused as soon as any
+    /// identity is allowed by name.
+    pub fn permits_identity(&self, identity: Option<&Identity>) -> bool {
+        match identity {
+            Some(identity) => {
+                !self.deny_identities.contains(identity)
+                    && (self.allow_identities.is_empty()
+                        || self.allow_identities.contains(identity))
+            }
+            None => self.allow_identities.is_empty(),
+        }
+    }
+
+    /// The limits set for `identity`, if any.
+    pub fn limits_for(&self, identity: &Identity) -> Option<&Limits> {
+        self.limits
+            .iter()
+            .find(|(limited, _)| limited == identity)
+            .map(|(_, limits)| limits)
+    }
+}
+
+impl FromStr for Acl {
+    type Err = String;
+
+    /// Parses an access list in the format described at the top of this
+    /// module. Limits not given on a `limit` line take their defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut acl = Acl::default();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            parse_rule(&mut acl, line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(acl)
+    }
+}
+
+fn parse_rule(acl: &mut Acl, line: &str) -> Result<(), String> {
+    let mut words = line.split_whitespace();
+    let action = words.next().unwrap_or_default();
+    let words: Vec<&str> = words.collect();
+    let is_identity = matches!(words.first(), Some(&"key" | &"psk"));
+    match (action, is_identity) {
+        ("allow", false) | ("deny", false) => {
+            let [cidr] = words[..] else {
+                return Err(format!("expected one address block: {}", line));
+            };
+            let cidr = cidr.parse()?;
+            match action {
+                "allow" => acl.allow.push(cidr),
+                _ => acl.deny.push(cidr),
+            }
+        }
+        ("allow", true) | ("deny", true) => {
+            let identity = parse_identity(&words, line)?;
+            match action {
+                "allow" => acl.allow_identities.push(identity),
+                _ => acl.deny_identities.push(identity),
+            }
+        }
+        ("limit", true) => {
+            let identity = parse_identity(&words[..2.min(words.len())], line)?;
+            let mut limits = Limits::default();
+            for setting in words.iter().skip(2) {
+                parse_limit(&mut limits, setting)?;
+            }
+            acl.limits.push((identity, limits));
+        }
+        _ => return Err(format!("not a rule: {}", line)),
+    }
+    Ok(())
+}
+
+fn parse_identity(words: &[&str], line: &str) -> Result<Identity, String> {
+    match words {
+        [kind, value] => format!("{} {}", kind, value).parse(),
+        _ => Err(format!("expected `key` or `psk` and one value: {}", line)),
+    }
+}
+
+fn parse_limit(limits: &mut Limits, setting: &str) -> Result<(), String> {
+    let error = || format!("not a limit: {}", setting);
+    let (name, value) = setting.split_once('=').ok_or_else(error)?;
+    let value: u64 = value.parse().map_err(|_| error())?;
+    let value_usize = || usize::try_from(value).map_err(|_| error());
+    match name {
+        "max_sessions" => limits.max_sessions = value_usize()?,
+        "max_objects_per_session" => limits.max_objects_per_session = value_usize()?,
+        "max_decoder_memory" => limits.max_decoder_memory = value,
+        "max_packets_per_second" => {
+            limits.max_packets_per_second = u32::try_from(value).map_err(|_| error())?
+        }
+        "idle_timeout" => limits.idle_timeout = Duration::from_secs(value),
+        _ => return Err(error()),
+    }
+    Ok(())
+}
+
+/// Parses a 256-bit key written as 64 hex digits.
+pub(crate) fn parse_key(hex: &str) -> Result<[u8; 32], String> {
+    let error = || format!("not a key of 64 hex digits: {}", hex);
+    let digits = hex.trim().as_bytes();
+    let mut key = [0u8; 32];
+    if digits.len() != 2 * key.len() {
+        return Err(error());
+    }
+    for (byte, pair) in key.iter_mut().zip(digits.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| error())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| error())?;
+    }
+    Ok(key)
+}
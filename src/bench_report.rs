@@ -0,0 +1,97 @@
+// bench_report.rs
+use nyxpsi::report::{size_label, BenchResults, Metric};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const START_MARKER: &str = "<!-- bench-results:start -->";
+const END_MARKER: &str = "<!-- bench-results:end -->";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let (results_path, plots) = paths_from_env();
+    let results = BenchResults::load(&results_path)?;
+    if results.records.is_empty() {
+        return Err(format!("{}: no results", results_path.display()).into());
+    }
+
+    let markdown = render(&results);
+    print!("{}", markdown);
+    fs::create_dir_all(&plots)?;
+    for size in results.payload_sizes() {
+        for metric in Metric::ALL {
+            let path = plots.join(format!("{}-{}.svg", metric.file_stem(), size));
+            fs::write(&path, results.svg_plot(size, metric))?;
+            eprintln!("Wrote {}", path.display());
+        }
+    }
+
+    if let Ok(readme) = std::env::var("NYXPSI_REPORT_README") {
+        update_readme(Path::new(&readme), &markdown)?;
+        eprintln!("Updated the results table in {}", readme);
+    }
+    Ok(())
+}
+
+/// A table per payload size, headed by the size if there are several.
+fn render(results: &BenchResults) -> String {
+    let sizes = results.payload_sizes();
+    sizes
+        .iter()
+        .map(|&size| {
+            let table = results.markdown_table(size);
+            if sizes.len() > 1 {
+                format!("**{}**\n\n{}", size_label(size), table)
+            } else {
+                table
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Replaces what is between the results markers in `readme` with
+/// `markdown`.
+fn update_readme(readme: &Path, markdown: &str) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(readme)?;
+    let (Some(start), Some(end)) = (text.find(START_MARKER), text.find(END_MARKER)) else {
+        return Err(format!(
+            "{}: no {} and {} around the results table",
+            readme.display(),
+            START_MARKER,
+            END_MARKER
+        )
+        .into());
+    };
+    if end < start {
+        return Err(format!("{}: results markers out of order", readme.display()).into());
+    }
+    let start = start + START_MARKER.len();
+    fs::write(
+        readme,
+        format!("{}\n{}{}", &text[..start], markdown, &text[end..]),
+    )?;
+    Ok(())
+}
+
+/// The results to read and the directory to write plots to.
+/// `NYXPSI_BENCH_RESULTS` names a CSV or JSON file of results, or the
+/// directory the benchmarks wrote theirs to (`target/nyxpsi-bench` by
+/// default), whose `results.json` is read. Plots go next to the results
+/// unless `NYXPSI_REPORT_PLOTS` names another directory.
+fn paths_from_env() -> (PathBuf, PathBuf) {
+    let results = PathBuf::from(
+        std::env::var("NYXPSI_BENCH_RESULTS").unwrap_or_else(|_| "target/nyxpsi-bench".into()),
+    );
+    let (results, directory) = if results.is_dir() {
+        (results.join("results.json"), results)
+    } else {
+        let directory = results
+            .parent()
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        (results, directory)
+    };
+    let plots = std::env::var("NYXPSI_REPORT_PLOTS").map_or(directory, PathBuf::from);
+    (results, plots)
+}
@@ -0,0 +1,24 @@
+// config.rs
+//
+// Settings the client, server and impairment proxy binaries share, read from
+// `NYXPSI_*` environment variables so that every binary reads them the same
+// way. Settings only one binary has stay with it.
+use crate::transport::TransportKind;
+
+/// The transports named by `NYXPSI_TRANSPORT`, a comma-separated list of
+/// `udp` and `udplite` in order of preference. Both, UDP-Lite first, by
+/// default.
+pub fn transports_from_env() -> Result<Vec<TransportKind>, String> {
+    match std::env::var("NYXPSI_TRANSPORT") {
+        Ok(names) => names.split(',').map(|name| name.trim().parse()).collect(),
+        Err(_) => Ok(vec![TransportKind::UdpLite, TransportKind::Udp]),
+    }
+}
+
+/// The pre-shared key in `NYXPSI_PSK`, if set. Both ends need the same one.
+#[cfg(feature = "crypto")]
+pub fn psk_from_env() -> Option<crate::crypto::Psk> {
+    std::env::var("NYXPSI_PSK")
+        .ok()
+        .map(|secret| crate::crypto::Psk::new(secret.as_bytes()))
+}
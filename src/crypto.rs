@@ -0,0 +1,706 @@
+// crypto.rs
+//
+// Optional authenticated encryption of every datagram with ChaCha20-Poly1305,
+// keyed from a pre-shared key or a Noise handshake. Built with the `crypto`
+// feature.
+//
+// A sealed datagram keeps the header readable and authenticates it along
+// with the session id and packet number; everything after the header is
+// encrypted:
+//
+// +--------+-----------------+---------------------+------------+-----+
+// | header | session id (u64)| packet number (u64) | ciphertext | tag |
+// +--------+-----------------+---------------------+------------+-----+
+//
+// Each session gets its own pair of keys, one per direction, derived from
+// the PSK and a session id the sender picks at random, or agreed in a
+// Noise_IK handshake over X25519 that also authenticates both ends by their
+// static keys. With both a PSK and static keys configured, the handshake
+// mixes in the PSK too (Noise_IKpsk2).
+//
+// Each direction of a session numbers its packets from zero, and the packet
+// number doubles as the nonce. The opening end keeps a sliding window of the
+// numbers it has seen, so that replayed datagrams (old feedback, say) are
+// rejected even though they authenticate.
+//
+// With padding on, the plaintext is followed by zeros and a u16 count of
+// them, and the padded bit in the packet type says so. Data packets are
+// padded to a multiple of a bucket size, capped at the largest data packet
+// in use, and control packets to one constant size, so that the lengths an
+// observer sees say little about symbol sizes or what kind of packet went
+// by. Probes only get the count, their sizes being the point of them.
+//
+// Keys are rotated as in QUIC (RFC 9001 section 6): once a direction has
+// sealed enough packets or bytes under its key, or held it long enough, the
+// next key is derived from it and the key-phase bit, the top bit of the
+// packet type in the header, flips. The opening end derives the same next
+// key when it first sees the bit flip, and keeps the one before for
+// stragglers numbered below the first packet of the new phase. Packet
+// numbers carry on across phases, so the replay window does too.
+use crate::acl::{parse_key, Identity};
+use crate::error::{NyxError, Result};
+use crate::packet::{Handshake, HEADER_SIZE, MAX_HANDSHAKE_MESSAGE, TYPE_DATA, TYPE_PROBE};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use rand::{rngs::OsRng, Rng};
+use snow::{
+    params::DHChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+    Builder, HandshakeState,
+};
+use std::{fmt, str::FromStr, time::Duration};
+use tokio::time::Instant;
+
+pub const SESSION_ID_SIZE: usize = 8;
+const PACKET_NUMBER_SIZE: usize = 8;
+const TAG_SIZE: usize = 16;
+/// Authenticated but unencrypted prefix of a sealed datagram.
+const AAD_SIZE: usize = HEADER_SIZE + SESSION_ID_SIZE + PACKET_NUMBER_SIZE;
+/// Bytes sealing adds to a datagram.
+pub const SEAL_OVERHEAD: usize = SESSION_ID_SIZE + PACKET_NUMBER_SIZE + TAG_SIZE;
+/// How far behind the highest packet number seen a packet may arrive and
+/// still be accepted.
+pub const REPLAY_WINDOW: u64 = 128;
+
+const PSK_CONTEXT: &str = "nyx-psi 2024 pre-shared key";
+const SENDER_KEY_CONTEXT: &str = "nyx-psi 2024 sender packet key";
+const RECEIVER_KEY_CONTEXT: &str = "nyx-psi 2024 receiver packet key";
+const KEY_UPDATE_CONTEXT: &str = "nyx-psi 2024 key update";
+/// Set in the packet type of a sealed datagram whose key is in odd phase.
+pub const KEY_PHASE_BIT: u8 = 0x80;
+/// Set in the packet type of a sealed datagram whose plaintext is padded.
+pub const PADDED_BIT: u8 = 0x40;
+/// Bytes at the end of a padded plaintext counting the padding before them.
+pub const PADDING_TRAILER_SIZE: usize = 2;
+/// Unsealed size control packets are padded to.
+pub const PADDED_CONTROL_SIZE: usize = 64;
+const NOISE_PARAMS: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+const NOISE_PSK_PARAMS: &str = "Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+/// Bound into every handshake along with the session id, so that a
+/// handshake cannot be replayed for another session.
+const PROLOGUE: &[u8] = b"nyx-psi 2024 handshake";
+
+/// A pre-shared key. Secrets of any length are accepted and hashed down to
+/// a 256-bit key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Psk([u8; 32]);
+
+impl Psk {
+    pub fn new(secret: &[u8]) -> Self {
+        Psk(blake3::derive_key(PSK_CONTEXT, secret))
+    }
+}
+
+impl fmt::Debug for Psk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Psk(..)")
+    }
+}
+
+/// An X25519 public key, written as 64 hex digits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for PublicKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        PublicKey(bytes)
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({})", self)
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_key(s).map(PublicKey)
+    }
+}
+
+/// A static X25519 keypair identifying one end of Noise handshakes. Parsed
+/// from the private key as 64 hex digits.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Keypair {
+    private: [u8; 32],
+    public: PublicKey,
+}
+
+impl Keypair {
+    pub fn generate() -> Self {
+        Self::from_private(rand::random())
+    }
+
+    pub fn from_private(private: [u8; 32]) -> Self {
+        let mut dh = DefaultResolver
+            .resolve_dh(&DHChoice::Curve25519)
+            .expect("built-in X25519");
+        dh.set(&private);
+        let public = dh.pubkey().try_into().expect("32-byte X25519 key");
+        Keypair {
+            private,
+            public: PublicKey(public),
+        }
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Keypair({}, ..)", self.public)
+    }
+}
+
+impl FromStr for Keypair {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_key(s).map(Self::from_private)
+    }
+}
+
+/// The sender's side of Noise handshakes: its own keypair and the static
+/// key the receiver must prove it holds.
+#[derive(Clone, Debug)]
+pub struct NoiseInitiator {
+    pub keypair: Keypair,
+    pub responder: PublicKey,
+}
+
+impl NoiseInitiator {
+    /// Starts a handshake for a new, randomly chosen session. Returns the
+    /// first message to send and the state to finish the handshake with.
+    pub fn initiate(&self, psk: Option<&Psk>) -> Result<(Handshake, Initiation)> {
+        let session_id = random_session_id();
+        let prologue = prologue(session_id);
+        let mut state = builder(psk, &prologue)
+            .local_private_key(&self.keypair.private)
+            .remote_public_key(self.responder.as_bytes())
+            .build_initiator()
+            .map_err(|_| NyxError::AuthFailure)?;
+        let mut message = vec![0u8; MAX_HANDSHAKE_MESSAGE];
+        let len = state
+            .write_message(&[], &mut message)
+            .map_err(|_| NyxError::AuthFailure)?;
+        message.truncate(len);
+        Ok((
+            Handshake {
+                session_id,
+                message,
+            },
+            Initiation { session_id, state },
+        ))
+    }
+}
+
+/// A handshake the sender has started and is waiting for the response to.
+pub struct Initiation {
+    session_id: u64,
+    state: HandshakeState,
+}
+
+impl Initiation {
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Completes the handshake with the receiver's response.
+    ///
+    /// Fails with [`NyxError::AuthFailure`] unless the response came from
+    /// the holder of the expected static key.
+    pub fn finish(mut self, response: &Handshake) -> Result<SessionKeys> {
+        if response.session_id != self.session_id {
+            return Err(NyxError::AuthFailure);
+        }
+        let mut payload = vec![0u8; MAX_HANDSHAKE_MESSAGE];
+        self.state
+            .read_message(&response.message, &mut payload)
+            .map_err(|_| NyxError::AuthFailure)?;
+        Ok(SessionKeys::from_handshake(
+            self.session_id,
+            &mut self.state,
+            Role::Sender,
+        ))
+    }
+}
+
+/// The receiver's side of Noise handshakes: its own keypair and the static
+/// keys of senders allowed to connect. An empty list admits any sender that
+/// completes the handshake.
+#[derive(Clone, Debug)]
+pub struct NoiseResponder {
+    pub keypair: Keypair,
+    pub authorized: Vec<PublicKey>,
+}
+
+impl NoiseResponder {
+    /// Answers a handshake, returning the response to send back and the
+    /// keys of the new session.
+    ///
+    /// Fails with [`NyxError::AuthFailure`] if the handshake message does
+    /// not check out or comes from a sender that is not authorized.
+    pub fn respond(&self, psk: Option<&Psk>, init: &Handshake) -> Result<(Handshake, SessionKeys)> {
+        let prologue = prologue(init.session_id);
+        let mut state = builder(psk, &prologue)
+            .local_private_key(&self.keypair.private)
+            .build_responder()
+            .map_err(|_| NyxError::AuthFailure)?;
+        let mut buf = vec![0u8; MAX_HANDSHAKE_MESSAGE];
+        state
+            .read_message(&init.message, &mut buf)
+            .map_err(|_| NyxError::AuthFailure)?;
+        let initiator = state
+            .get_remote_static()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .map(PublicKey)
+            .ok_or(NyxError::AuthFailure)?;
+        if !self.authorized.is_empty() && !self.authorized.contains(&initiator) {
+            return Err(NyxError::AuthFailure);
+        }
+        let len = state
+            .write_message(&[], &mut buf)
+            .map_err(|_| NyxError::AuthFailure)?;
+        buf.truncate(len);
+        let keys = SessionKeys::from_handshake(init.session_id, &mut state, Role::Receiver)
+            .with_identity(Identity::Key(initiator.0));
+        Ok((
+            Handshake {
+                session_id: init.session_id,
+                message: buf,
+            },
+            keys,
+        ))
+    }
+}
+
+/// Which end of a session the keys belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Sender,
+    Receiver,
+}
+
+/// Remembers which packet numbers have been opened, within
+/// [`REPLAY_WINDOW`] of the highest one.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayWindow {
+    /// One past the highest packet number seen, zero before the first.
+    next: u64,
+    /// Bit `i` is set if packet number `next - 1 - i` has been seen.
+    seen: u128,
+}
+
+impl ReplayWindow {
+    /// Whether `packet_number` is new: neither seen before nor too old to
+    /// tell.
+    pub fn is_fresh(&self, packet_number: u64) -> bool {
+        if packet_number >= self.next {
+            return true;
+        }
+        let age = self.next - 1 - packet_number;
+        age < REPLAY_WINDOW && self.seen & (1 << age) == 0
+    }
+
+    /// Records `packet_number` as seen, sliding the window forward if it is
+    /// the highest yet.
+    pub fn mark(&mut self, packet_number: u64) {
+        if packet_number >= self.next {
+            let shift = packet_number - self.next + 1;
+            self.seen = if shift < REPLAY_WINDOW {
+                self.seen << shift
+            } else {
+                0
+            };
+            self.seen |= 1;
+            self.next = packet_number + 1;
+        } else {
+            let age = self.next - 1 - packet_number;
+            if age < REPLAY_WINDOW {
+                self.seen |= 1 << age;
+            }
+        }
+    }
+}
+
+/// When a direction of a session moves on to its next key. Whichever
+/// threshold is reached first triggers the update. The opening end can only
+/// follow one update at a time, so thresholds should be far above the
+/// number of packets in flight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    pub max_packets: u64,
+    pub max_bytes: u64,
+    pub max_age: Duration,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        RekeyPolicy {
+            max_packets: 1 << 32,
+            max_bytes: 1 << 40,
+            max_age: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// How sealed datagrams are padded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Padding {
+    /// Data packets are padded up to a multiple of this many bytes, or to
+    /// the size of the largest data packet in use if that is smaller.
+    pub bucket: usize,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding { bucket: 256 }
+    }
+}
+
+/// One direction's key in one phase.
+struct PhaseKey {
+    secret: [u8; 32],
+    cipher: ChaCha20Poly1305,
+    phase: bool,
+}
+
+impl PhaseKey {
+    fn new(secret: [u8; 32]) -> Self {
+        PhaseKey {
+            secret,
+            cipher: ChaCha20Poly1305::new(&secret.into()),
+            phase: false,
+        }
+    }
+
+    fn next(&self) -> Self {
+        PhaseKey {
+            phase: !self.phase,
+            ..PhaseKey::new(blake3::derive_key(KEY_UPDATE_CONTEXT, &self.secret))
+        }
+    }
+}
+
+/// Seals outgoing and opens incoming datagrams of one session.
+pub struct SessionKeys {
+    session_id: u64,
+    seal: PhaseKey,
+    open: PhaseKey,
+    /// The opening key of the phase before, with the lowest packet number
+    /// seen in the current one: anything numbered below it is a straggler.
+    previous_open: Option<(PhaseKey, u64)>,
+    rekey: RekeyPolicy,
+    /// Packets and bytes sealed under the current key, and since when.
+    sealed_packets: u64,
+    sealed_bytes: u64,
+    seal_phase_started: Instant,
+    seal_updates: u64,
+    open_updates: u64,
+    padding: Option<Padding>,
+    /// No data packet is padded beyond this size, unsealed.
+    padding_limit: usize,
+    next_packet_number: u64,
+    replay: ReplayWindow,
+    identity: Option<Identity>,
+}
+
+impl SessionKeys {
+    pub fn new(psk: &Psk, session_id: u64, role: Role) -> Self {
+        let sender = session_secret(psk, session_id, SENDER_KEY_CONTEXT);
+        let receiver = session_secret(psk, session_id, RECEIVER_KEY_CONTEXT);
+        Self::with_secrets(session_id, sender, receiver, role)
+    }
+
+    /// Keys from a completed handshake. Its first key protects what the
+    /// sender, as the initiator, sends.
+    fn from_handshake(session_id: u64, state: &mut HandshakeState, role: Role) -> Self {
+        let (sender, receiver) = state.dangerously_get_raw_split();
+        Self::with_secrets(session_id, sender, receiver, role)
+    }
+
+    fn with_secrets(session_id: u64, sender: [u8; 32], receiver: [u8; 32], role: Role) -> Self {
+        let (seal, open) = match role {
+            Role::Sender => (sender, receiver),
+            Role::Receiver => (receiver, sender),
+        };
+        SessionKeys {
+            session_id,
+            seal: PhaseKey::new(seal),
+            open: PhaseKey::new(open),
+            previous_open: None,
+            rekey: RekeyPolicy::default(),
+            sealed_packets: 0,
+            sealed_bytes: 0,
+            seal_phase_started: Instant::now(),
+            seal_updates: 0,
+            open_updates: 0,
+            padding: None,
+            padding_limit: usize::MAX,
+            next_packet_number: 0,
+            replay: ReplayWindow::default(),
+            identity: None,
+        }
+    }
+
+    /// The same keys, updated under `policy` rather than the default one.
+    pub fn with_rekey(mut self, policy: RekeyPolicy) -> Self {
+        self.rekey = policy;
+        self
+    }
+
+    /// The same keys, padding what they seal under `padding`.
+    pub fn with_padding(mut self, padding: Option<Padding>) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Caps data packet padding at `max_len` bytes, unsealed: the size of
+    /// the largest data packet the sender currently sends, trailer
+    /// included.
+    pub fn set_padding_limit(&mut self, max_len: usize) {
+        self.padding_limit = max_len;
+    }
+
+    /// The same keys, recorded as authenticating `identity`.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Who the peer proved to be in establishing these keys, if anything
+    /// more than knowing an unnamed PSK.
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// How many times the sealing key has been updated.
+    pub fn seal_updates(&self) -> u64 {
+        self.seal_updates
+    }
+
+    /// How many of the peer's key updates have been followed.
+    pub fn open_updates(&self) -> u64 {
+        self.open_updates
+    }
+
+    /// Seals a serialized packet under the next packet number, first moving
+    /// on to the next key if the current one is due for an update.
+    pub fn seal(&mut self, datagram: &[u8]) -> Vec<u8> {
+        assert!(datagram.len() >= HEADER_SIZE, "datagram without header");
+        if self.sealed_packets >= self.rekey.max_packets
+            || self.sealed_bytes >= self.rekey.max_bytes
+            || self.seal_phase_started.elapsed() >= self.rekey.max_age
+        {
+            self.seal = self.seal.next();
+            self.sealed_packets = 0;
+            self.sealed_bytes = 0;
+            self.seal_phase_started = Instant::now();
+            self.seal_updates += 1;
+        }
+        let mut body = datagram[HEADER_SIZE..].to_vec();
+        let padded_len = self.padded_len(datagram);
+        if let Some(len) = padded_len {
+            let zeros = len - datagram.len() - PADDING_TRAILER_SIZE;
+            body.resize(body.len() + zeros, 0);
+            body.extend_from_slice(&(zeros as u16).to_be_bytes());
+        }
+        self.sealed_packets += 1;
+        self.sealed_bytes += (HEADER_SIZE + body.len()) as u64;
+        let packet_number = self.next_packet_number;
+        self.next_packet_number += 1;
+        let mut sealed = Vec::with_capacity(HEADER_SIZE + body.len() + SEAL_OVERHEAD);
+        sealed.extend_from_slice(&datagram[..HEADER_SIZE]);
+        if self.seal.phase {
+            sealed[1] |= KEY_PHASE_BIT;
+        }
+        if padded_len.is_some() {
+            sealed[1] |= PADDED_BIT;
+        }
+        sealed.extend_from_slice(&self.session_id.to_be_bytes());
+        sealed.extend_from_slice(&packet_number.to_be_bytes());
+        let nonce = nonce(packet_number);
+        let ciphertext = self
+            .seal
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &body,
+                    aad: &sealed,
+                },
+            )
+            .expect("datagram fits ChaCha20-Poly1305 limits");
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// The unsealed size `datagram` is padded to, trailer included, if
+    /// padding is on.
+    fn padded_len(&self, datagram: &[u8]) -> Option<usize> {
+        let padding = self.padding?;
+        let min = datagram.len() + PADDING_TRAILER_SIZE;
+        let len = match datagram[1] {
+            TYPE_DATA => min
+                .next_multiple_of(padding.bucket.max(1))
+                .min(self.padding_limit),
+            TYPE_PROBE => min,
+            _ => PADDED_CONTROL_SIZE,
+        };
+        Some(len.clamp(min, u16::MAX as usize))
+    }
+
+    /// Authenticates and decrypts a sealed datagram, returning the packet
+    /// inside for [`Packet::parse`](crate::packet::Packet::parse). A
+    /// datagram in the next key phase that authenticates under the next key
+    /// moves the session on to it.
+    ///
+    /// Fails with [`NyxError::Replayed`] for a packet number opened before,
+    /// or too far behind the latest to tell.
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < AAD_SIZE + TAG_SIZE {
+            return Err(NyxError::MalformedPacket("shorter than sealed header"));
+        }
+        if peek_session_id(sealed) != Some(self.session_id) {
+            return Err(NyxError::AuthFailure);
+        }
+        let (aad, ciphertext) = sealed.split_at(AAD_SIZE);
+        let packet_number = u64::from_be_bytes(
+            aad[HEADER_SIZE + SESSION_ID_SIZE..]
+                .try_into()
+                .expect("sliced to size"),
+        );
+        if !self.replay.is_fresh(packet_number) {
+            return Err(NyxError::Replayed { packet_number });
+        }
+        let phase = aad[1] & KEY_PHASE_BIT != 0;
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        let nonce = nonce(packet_number);
+        let body = if phase == self.open.phase {
+            self.open.cipher.decrypt(&nonce, payload)
+        } else {
+            match &self.previous_open {
+                Some((previous, first)) if packet_number < *first => {
+                    previous.cipher.decrypt(&nonce, payload)
+                }
+                _ => {
+                    let next = self.open.next();
+                    let body = next.cipher.decrypt(&nonce, payload);
+                    if body.is_ok() {
+                        let previous = std::mem::replace(&mut self.open, next);
+                        self.previous_open = Some((previous, packet_number));
+                        self.open_updates += 1;
+                    }
+                    body
+                }
+            }
+        }
+        .map_err(|_| NyxError::AuthFailure)?;
+        let body = if aad[1] & PADDED_BIT != 0 {
+            unpad(&body).ok_or(NyxError::MalformedPacket("padding longer than datagram"))?
+        } else {
+            &body
+        };
+        if let Some((_, first)) = self
+            .previous_open
+            .as_mut()
+            .filter(|_| phase == self.open.phase)
+        {
+            *first = (*first).min(packet_number);
+        }
+        let mut datagram = Vec::with_capacity(HEADER_SIZE + body.len());
+        datagram.extend_from_slice(&sealed[..HEADER_SIZE]);
+        datagram[1] &= !(KEY_PHASE_BIT | PADDED_BIT);
+        datagram.extend_from_slice(body);
+        self.replay.mark(packet_number);
+        Ok(datagram)
+    }
+}
+
+/// A padded plaintext without its padding.
+fn unpad(body: &[u8]) -> Option<&[u8]> {
+    let trailer = body.len().checked_sub(PADDING_TRAILER_SIZE)?;
+    let zeros = u16::from_be_bytes([body[trailer], body[trailer + 1]]) as usize;
+    Some(&body[..trailer.checked_sub(zeros)?])
+}
+
+/// The session a sealed datagram claims to belong to, read without
+/// authenticating anything.
+pub fn peek_session_id(sealed: &[u8]) -> Option<u64> {
+    let id = sealed.get(HEADER_SIZE..HEADER_SIZE + SESSION_ID_SIZE)?;
+    Some(u64::from_be_bytes(id.try_into().expect("sliced to size")))
+}
+
+/// The packet number of a sealed datagram, read without authenticating
+/// anything.
+pub fn peek_packet_number(sealed: &[u8]) -> Option<u64> {
+    let start = HEADER_SIZE + SESSION_ID_SIZE;
+    let number = sealed.get(start..start + PACKET_NUMBER_SIZE)?;
+    Some(u64::from_be_bytes(
+        number.try_into().expect("sliced to size"),
+    ))
+}
+
+/// A fresh session id for a sender to pick. It keys the session along with
+/// the PSK, so it always comes straight from the OS, never from a seed.
+pub fn random_session_id() -> u64 {
+    OsRng.gen()
+}
+
+fn builder<'a>(psk: Option<&'a Psk>, prologue: &'a [u8]) -> Builder<'a> {
+    let params = match psk {
+        Some(_) => NOISE_PSK_PARAMS,
+        None => NOISE_PARAMS,
+    };
+    let builder = Builder::new(params.parse().expect("valid Noise parameters")).prologue(prologue);
+    match psk {
+        Some(psk) => builder.psk(2, &psk.0),
+        None => builder,
+    }
+}
+
+fn prologue(session_id: u64) -> Vec<u8> {
+    let mut prologue = PROLOGUE.to_vec();
+    prologue.extend_from_slice(&session_id.to_be_bytes());
+    prologue
+}
+
+fn session_secret(psk: &Psk, session_id: u64, context: &str) -> [u8; 32] {
+    let mut material = [0u8; 32 + SESSION_ID_SIZE];
+    material[..32].copy_from_slice(&psk.0);
+    material[32..].copy_from_slice(&session_id.to_be_bytes());
+    blake3::derive_key(context, &material)
+}
+
+/// `0 0 0 0 | packet number`.
+fn nonce(packet_number: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&packet_number.to_be_bytes());
+    nonce.into()
+}
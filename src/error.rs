@@ -0,0 +1,81 @@
+// error.rs
+use std::{error::Error, fmt, io};
+
+/// Errors returned by the nyx-ψ send and receive APIs.
+#[derive(Debug)]
+pub enum NyxError {
+    /// The underlying socket failed.
+    Io(io::Error),
+    /// No response arrived before the configured deadline.
+    Timeout,
+    /// A datagram could not be parsed.
+    MalformedPacket(&'static str),
+    /// The peer speaks a different protocol version.
+    VersionMismatch { expected: u8, found: u8 },
+    /// A data packet was damaged in flight. With UDP-Lite the header is
+    /// still intact, so the object it belonged to is known.
+    ChecksumMismatch { object_id: u32 },
+    /// An object decoded to bytes that do not match its announced digest.
+    IntegrityFailure { object_id: u32 },
+    /// A datagram or handshake failed authentication.
+    AuthFailure,
+    /// An authenticated datagram was a replay of one already received.
+    Replayed { packet_number: u64 },
+    /// A configured limit was exceeded.
+    ResourceLimit(&'static str),
+    /// A peer authenticated as an identity the access list refuses.
+    Denied,
+    /// The caller passed something that cannot be sent.
+    InvalidInput(&'static str),
+}
+
+impl fmt::Display for NyxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NyxError::Io(e) => write!(f, "I/O error: {}", e),
+            NyxError::Timeout => write!(f, "timed out"),
+            NyxError::MalformedPacket(reason) => write!(f, "malformed packet: {}", reason),
+            NyxError::VersionMismatch { expected, found } => write!(
+                f,
+                "protocol version mismatch: expected {}, found {}",
+                expected, found
+            ),
+            NyxError::ChecksumMismatch { object_id } => {
+                write!(f, "checksum mismatch in a symbol of object {}", object_id)
+            }
+            NyxError::IntegrityFailure { object_id } => {
+                write!(f, "object {} failed its integrity check", object_id)
+            }
+            NyxError::AuthFailure => write!(f, "authentication failed"),
+            NyxError::Replayed { packet_number } => {
+                write!(f, "packet {} was replayed", packet_number)
+            }
+            NyxError::ResourceLimit(limit) => write!(f, "resource limit exceeded: {}", limit),
+            NyxError::Denied => write!(f, "refused by the access list"),
+            NyxError::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+        }
+    }
+}
+
+impl Error for NyxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NyxError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NyxError {
+    fn from(e: io::Error) -> Self {
+        NyxError::Io(e)
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for NyxError {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        NyxError::Timeout
+    }
+}
+
+pub type Result<T> = std::result::Result<T, NyxError>;
@@ -0,0 +1,26 @@
+pub mod acl;
+pub mod config;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+pub mod error;
+pub mod limits;
+pub mod packet;
+pub mod pmtu;
+pub mod proxy;
+pub mod receiver;
+pub mod report;
+pub mod sender;
+pub mod stats;
+pub mod transport;
+pub mod validation;
+
+// The file keeps its own `mod tests` block, as it had before the library
+// split.
+#[allow(clippy::module_inception)]
+mod tests;
+
+pub use error::{NyxError, Result};
+pub use receiver::{
+    Reassembler, ReceiveEvent, ReceivedObject, Receiver, ReceiverConfig, ReceiverStats,
+};
+pub use sender::{Delivery, Sender, SenderConfig};
@@ -0,0 +1,79 @@
+// limits.rs
+//
+// Bounds on what senders can make a receiver hold or do, so that one
+// misbehaving peer cannot exhaust memory or crowd out the others. Traffic
+// beyond a limit is dropped and counted in `ReceiverStats`.
+use raptorq::ObjectTransmissionInformation;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Peers the receiver keeps state for at once. Datagrams from further
+    /// peers are dropped until an idle session can make way.
+    pub max_sessions: usize,
+    /// Objects one peer may have being decoded at once. A symbol of a
+    /// further object evicts the peer's least recently active one.
+    pub max_objects_per_session: usize,
+    /// Bytes all decoders together may hold, as estimated by
+    /// [`decoder_cost`]. Objects that do not fit are dropped.
+    pub max_decoder_memory: u64,
+    /// Datagrams per second accepted from one peer, with bursts of up to a
+    /// second's worth.
+    pub max_packets_per_second: u32,
+    /// How long a peer has to be silent before its session may be evicted
+    /// to make way for others.
+    pub idle_timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_sessions: 1024,
+            max_objects_per_session: 4,
+            max_decoder_memory: 256 << 20,
+            max_packets_per_second: 20_000,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Estimated memory a decoder for an object takes: the object rounded up
+/// to whole symbols, once for the symbols it buffers and once more for the
+/// intermediate symbols and the decoded result.
+pub fn decoder_cost(oti: &ObjectTransmissionInformation) -> u64 {
+    let symbol_size = oti.symbol_size().max(1) as u64;
+    2 * oti.transfer_length().div_ceil(symbol_size) * symbol_size
+}
+
+/// A token bucket holding up to a second's worth of packets.
+#[derive(Clone, Debug)]
+pub(crate) struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(packets_per_second: u32, now: Instant) -> Self {
+        let rate = packets_per_second as f64;
+        RateLimiter {
+            rate,
+            tokens: rate,
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token for one packet, if there is one.
+    pub(crate) fn allow(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,5 +1,3 @@
-mod tests;
-
 fn main() {
     println!("Run 'cargo test' to execute the unit tests.");
 }
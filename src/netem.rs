@@ -0,0 +1,109 @@
+// netem.rs
+use nyxpsi::{
+    proxy::{Proxy, ProxyConfig},
+    transport::{LinkConfig, LinkStats, Trace},
+};
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let defaults = ProxyConfig::default();
+    let (up, down) = links_from_env()?;
+    let config = ProxyConfig {
+        transports: nyxpsi::config::transports_from_env()?,
+        listen: addr_from_env("NYXPSI_NETEM_LISTEN", defaults.listen)?,
+        upstream: addr_from_env("NYXPSI_NETEM_UPSTREAM", defaults.upstream)?,
+        up,
+        down,
+        ..defaults
+    };
+    println!("Client to server: {:?}", config.up);
+    println!("Server to client: {:?}", config.down);
+    let proxy = Proxy::bind(config.clone())?;
+    println!(
+        "Proxy listening on: {} over {}, forwarding to {}",
+        proxy.local_addr(),
+        proxy
+            .transport_kinds()
+            .iter()
+            .map(|kind| kind.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        config.upstream
+    );
+
+    let report = async {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        let mut last = proxy.stats();
+        loop {
+            interval.tick().await;
+            let stats = proxy.stats();
+            if stats.clients != last.clients {
+                println!("{} clients", stats.clients);
+            }
+            if stats.up != last.up || stats.down != last.down {
+                println!(
+                    "Client to server: {} | Server to client: {}",
+                    describe(&stats.up, &last.up),
+                    describe(&stats.down, &last.down)
+                );
+            }
+            last = stats;
+        }
+    };
+    tokio::select! {
+        result = proxy.run() => result?,
+        _ = report => {}
+    }
+    Ok(())
+}
+
+/// What a link did to datagrams since `last`.
+fn describe(stats: &LinkStats, last: &LinkStats) -> String {
+    format!(
+        "{} sent, {} lost, {} over the rate, {} reordered, {} duplicated, {} corrupted",
+        stats.sent - last.sent,
+        stats.lost - last.lost,
+        stats.overflowed - last.overflowed,
+        stats.reordered - last.reordered,
+        stats.duplicated - last.duplicated,
+        stats.corrupted - last.corrupted
+    )
+}
+
+/// The links each way. `NYXPSI_NETEM` sets both, as space-separated
+/// `name=value` settings such as `loss=burst:0.1,5 delay=50 jitter=10`;
+/// `NYXPSI_NETEM_UP` and `NYXPSI_NETEM_DOWN` set the client-to-server and
+/// server-to-client links on their own. `NYXPSI_NETEM_TRACE` names a loss and
+/// delay trace for both to replay. A perfect link by default.
+fn links_from_env() -> Result<(LinkConfig, LinkConfig), Box<dyn Error>> {
+    let both: LinkConfig = match std::env::var("NYXPSI_NETEM") {
+        Ok(settings) => settings.parse()?,
+        Err(_) => LinkConfig::default(),
+    };
+    let mut up = match std::env::var("NYXPSI_NETEM_UP") {
+        Ok(settings) => settings.parse()?,
+        Err(_) => both.clone(),
+    };
+    let mut down = match std::env::var("NYXPSI_NETEM_DOWN") {
+        Ok(settings) => settings.parse()?,
+        // Not the same random choices as the other way.
+        Err(_) => LinkConfig {
+            seed: both.seed.wrapping_add(1 << 32),
+            ..both
+        },
+    };
+    if let Ok(path) = std::env::var("NYXPSI_NETEM_TRACE") {
+        let trace = Trace::load(path)?;
+        up.trace = Some(trace.clone());
+        down.trace = Some(trace);
+    }
+    Ok((up, down))
+}
+
+fn addr_from_env(name: &str, default: SocketAddr) -> Result<SocketAddr, Box<dyn Error>> {
+    match std::env::var(name) {
+        Ok(addr) => Ok(addr.parse()?),
+        Err(_) => Ok(default),
+    }
+}
@@ -0,0 +1,451 @@
+// packet.rs
+use crate::error::{NyxError, Result};
+use crate::stats::MAX_JUMBO_SYMBOL_SIZE;
+use raptorq::{EncodingPacket, ObjectTransmissionInformation};
+
+pub const PROTOCOL_VERSION: u8 = 5;
+pub const HEADER_SIZE: usize = 6;
+pub const CHECKSUM_SIZE: usize = 4;
+/// Leading bytes of a datagram that must arrive intact for it to be
+/// attributed to an object: the header and, in data packets, the symbol
+/// checksum. UDP-Lite checksums exactly these.
+pub const COVERED_SIZE: usize = HEADER_SIZE + CHECKSUM_SIZE;
+pub const OTI_SIZE: usize = 12;
+pub const DIGEST_SIZE: usize = 32;
+pub const PAYLOAD_ID_SIZE: usize = 4;
+/// Bytes a data datagram carries on top of its symbol.
+pub const DATA_OVERHEAD: usize = COVERED_SIZE + OTI_SIZE + DIGEST_SIZE + PAYLOAD_ID_SIZE;
+/// Leading bytes of an object's digest that its feedback echoes.
+pub const FEEDBACK_DIGEST_SIZE: usize = 8;
+pub const FEEDBACK_SIZE: usize = HEADER_SIZE + 2 + FEEDBACK_DIGEST_SIZE;
+pub const PROBE_ACK_SIZE: usize = HEADER_SIZE + 2;
+pub const COOKIE_SIZE: usize = 16;
+/// Hellos carry a cookie field whether or not they have a cookie to echo,
+/// so that the retry answering them is no larger.
+pub const HELLO_SIZE: usize = HEADER_SIZE + COOKIE_SIZE;
+pub const HELLO_ACK_SIZE: usize = HEADER_SIZE;
+pub const RETRY_SIZE: usize = HEADER_SIZE + COOKIE_SIZE;
+/// Bytes a handshake packet carries on top of its handshake message.
+pub const HANDSHAKE_OVERHEAD: usize = HEADER_SIZE + 8;
+/// Longest handshake message accepted, well above what the handshakes in
+/// use produce.
+pub const MAX_HANDSHAKE_MESSAGE: usize = 256;
+
+/// K'_max from RFC 6330 section 5.1.2.
+const MAX_SOURCE_SYMBOLS_PER_BLOCK: u64 = 56403;
+
+pub(crate) const TYPE_DATA: u8 = 0;
+const TYPE_FEEDBACK: u8 = 1;
+pub(crate) const TYPE_PROBE: u8 = 2;
+const TYPE_PROBE_ACK: u8 = 3;
+const TYPE_HELLO: u8 = 4;
+const TYPE_HELLO_ACK: u8 = 5;
+const TYPE_HANDSHAKE_INIT: u8 = 6;
+const TYPE_HANDSHAKE_RESPONSE: u8 = 7;
+const TYPE_RETRY: u8 = 8;
+
+/// Common prefix of every datagram:
+///
+/// ```text
+/// +---------+------+-----------------+
+/// | version | type | object id (u32) |
+/// +---------+------+-----------------+
+/// ```
+///
+/// Probes and their acks carry a probe sequence number in place of the
+/// object id, hellos, retries and hello acks a nonce. Handshake packets leave it
+/// zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub packet_type: u8,
+    pub object_id: u32,
+}
+
+impl Header {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(PROTOCOL_VERSION);
+        out.push(self.packet_type);
+        out.extend_from_slice(&self.object_id.to_be_bytes());
+    }
+
+    fn parse(buf: &[u8]) -> Result<Header> {
+        if buf.len() < HEADER_SIZE {
+            return Err(NyxError::MalformedPacket("shorter than header"));
+        }
+        if buf[0] != PROTOCOL_VERSION {
+            return Err(NyxError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                found: buf[0],
+            });
+        }
+        Ok(Header {
+            packet_type: buf[1],
+            object_id: u32::from_be_bytes([buf[2], buf[3], buf[4], buf[5]]),
+        })
+    }
+}
+
+/// One encoding symbol together with what the receiver needs to know about
+/// its object: the OTI to build a decoder, and a BLAKE3 digest of the whole
+/// object to check the decoded bytes against.
+///
+/// On the wire the header is followed by a CRC32C of the rest of the
+/// datagram (header included, the checksum field itself excluded), so that
+/// symbols damaged in flight are dropped instead of poisoning the decode:
+///
+/// ```text
+/// +--------+-------------+-----+--------+------------+--------+
+/// | header | crc32c (u32)| OTI | digest | payload id | symbol |
+/// +--------+-------------+-----+--------+------------+--------+
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataPacket {
+    pub object_id: u32,
+    pub oti: ObjectTransmissionInformation,
+    pub digest: [u8; DIGEST_SIZE],
+    pub packet: EncodingPacket,
+}
+
+/// Sent by the receiver once an object has been decoded. Echoes the start
+/// of the object's digest, so that it acknowledges that object and not just
+/// whichever one last had the same id; someone who never saw the object's
+/// symbols cannot forge it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Feedback {
+    pub object_id: u32,
+    pub next_symbol_size: u16,
+    pub digest: [u8; FEEDBACK_DIGEST_SIZE],
+}
+
+/// A path MTU probe, padded out to `size` bytes on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Probe {
+    pub seq: u32,
+    pub size: u16,
+}
+
+/// Confirms a probe of `size` bytes arrived intact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProbeAck {
+    pub seq: u32,
+    pub size: u16,
+}
+
+/// Opens a session: the sender tries each transport in turn until one
+/// carries a hello to the receiver and its ack back. A receiver validating
+/// addresses first answers with a [`Retry`], whose cookie the next hello
+/// echoes; until then the cookie is all zeros.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub nonce: u32,
+    pub cookie: [u8; COOKIE_SIZE],
+}
+
+/// Answers a [`Hello`] from an address the receiver has not validated,
+/// echoing its nonce, with a cookie for the sender to prove it received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retry {
+    pub nonce: u32,
+    pub cookie: [u8; COOKIE_SIZE],
+}
+
+/// Answers a [`Hello`], echoing its nonce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HelloAck {
+    pub nonce: u32,
+}
+
+/// One message of a key exchange, for the session the sender picked
+/// `session_id` for. Always sent in the clear:
+///
+/// ```text
+/// +--------+-------------------+-------------------+
+/// | header | session id (u64)  | handshake message |
+/// +--------+-------------------+-------------------+
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    pub session_id: u64,
+    pub message: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    Data(DataPacket),
+    Feedback(Feedback),
+    Probe(Probe),
+    ProbeAck(ProbeAck),
+    Hello(Hello),
+    HelloAck(HelloAck),
+    Retry(Retry),
+    HandshakeInit(Handshake),
+    HandshakeResponse(Handshake),
+}
+
+impl Packet {
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            Packet::Data(data) => {
+                let mut out = Vec::with_capacity(DATA_OVERHEAD + data.packet.data().len());
+                Header {
+                    packet_type: TYPE_DATA,
+                    object_id: data.object_id,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&[0; CHECKSUM_SIZE]);
+                out.extend_from_slice(&data.oti.serialize());
+                out.extend_from_slice(&data.digest);
+                out.extend_from_slice(&data.packet.serialize());
+                let checksum = symbol_checksum(&out);
+                out[HEADER_SIZE..COVERED_SIZE].copy_from_slice(&checksum.to_be_bytes());
+                out
+            }
+            Packet::Feedback(feedback) => {
+                let mut out = Vec::with_capacity(FEEDBACK_SIZE);
+                Header {
+                    packet_type: TYPE_FEEDBACK,
+                    object_id: feedback.object_id,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&feedback.next_symbol_size.to_be_bytes());
+                out.extend_from_slice(&feedback.digest);
+                out
+            }
+            Packet::Probe(probe) => {
+                let size = (probe.size as usize).max(HEADER_SIZE);
+                let mut out = Vec::with_capacity(size);
+                Header {
+                    packet_type: TYPE_PROBE,
+                    object_id: probe.seq,
+                }
+                .write(&mut out);
+                out.resize(size, 0);
+                out
+            }
+            Packet::ProbeAck(ack) => {
+                let mut out = Vec::with_capacity(PROBE_ACK_SIZE);
+                Header {
+                    packet_type: TYPE_PROBE_ACK,
+                    object_id: ack.seq,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&ack.size.to_be_bytes());
+                out
+            }
+            Packet::Hello(hello) => {
+                let mut out = Vec::with_capacity(HELLO_SIZE);
+                Header {
+                    packet_type: TYPE_HELLO,
+                    object_id: hello.nonce,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&hello.cookie);
+                out
+            }
+            Packet::Retry(retry) => {
+                let mut out = Vec::with_capacity(RETRY_SIZE);
+                Header {
+                    packet_type: TYPE_RETRY,
+                    object_id: retry.nonce,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&retry.cookie);
+                out
+            }
+            Packet::HelloAck(ack) => {
+                let mut out = Vec::with_capacity(HELLO_ACK_SIZE);
+                Header {
+                    packet_type: TYPE_HELLO_ACK,
+                    object_id: ack.nonce,
+                }
+                .write(&mut out);
+                out
+            }
+            Packet::HandshakeInit(handshake) | Packet::HandshakeResponse(handshake) => {
+                let packet_type = match self {
+                    Packet::HandshakeInit(_) => TYPE_HANDSHAKE_INIT,
+                    _ => TYPE_HANDSHAKE_RESPONSE,
+                };
+                let mut out = Vec::with_capacity(HANDSHAKE_OVERHEAD + handshake.message.len());
+                Header {
+                    packet_type,
+                    object_id: 0,
+                }
+                .write(&mut out);
+                out.extend_from_slice(&handshake.session_id.to_be_bytes());
+                out.extend_from_slice(&handshake.message);
+                out
+            }
+        }
+    }
+
+    pub fn parse(buf: &[u8]) -> Result<Packet> {
+        let header = Header::parse(buf)?;
+        let body = &buf[HEADER_SIZE..];
+        match header.packet_type {
+            TYPE_DATA => {
+                if buf.len() < DATA_OVERHEAD {
+                    return Err(NyxError::MalformedPacket("data packet too short"));
+                }
+                let (checksum, body) = body.split_at(CHECKSUM_SIZE);
+                if checksum != symbol_checksum(buf).to_be_bytes() {
+                    return Err(NyxError::ChecksumMismatch {
+                        object_id: header.object_id,
+                    });
+                }
+                let (oti, body) = body.split_at(OTI_SIZE);
+                let (digest, symbol) = body.split_at(DIGEST_SIZE);
+                let oti: &[u8; OTI_SIZE] = oti.try_into().expect("split at OTI_SIZE");
+                if oti[5] != 0 {
+                    return Err(NyxError::MalformedPacket("reserved OTI byte set"));
+                }
+                let oti = ObjectTransmissionInformation::deserialize(oti);
+                validate_oti(&oti)?;
+
+                let packet = EncodingPacket::deserialize(symbol);
+                if packet.data().len() != oti.symbol_size() as usize {
+                    return Err(NyxError::MalformedPacket("symbol length differs from OTI"));
+                }
+                if packet.payload_id().source_block_number() >= oti.source_blocks() {
+                    return Err(NyxError::MalformedPacket("source block out of range"));
+                }
+                Ok(Packet::Data(DataPacket {
+                    object_id: header.object_id,
+                    oti,
+                    digest: digest.try_into().expect("split at DIGEST_SIZE"),
+                    packet,
+                }))
+            }
+            TYPE_FEEDBACK => {
+                if buf.len() != FEEDBACK_SIZE {
+                    return Err(NyxError::MalformedPacket("bad feedback length"));
+                }
+                Ok(Packet::Feedback(Feedback {
+                    object_id: header.object_id,
+                    next_symbol_size: u16::from_be_bytes([body[0], body[1]]),
+                    digest: body[2..].try_into().expect("checked length"),
+                }))
+            }
+            TYPE_PROBE => {
+                let size = u16::try_from(buf.len())
+                    .map_err(|_| NyxError::MalformedPacket("probe too large"))?;
+                Ok(Packet::Probe(Probe {
+                    seq: header.object_id,
+                    size,
+                }))
+            }
+            TYPE_PROBE_ACK => {
+                if buf.len() != PROBE_ACK_SIZE {
+                    return Err(NyxError::MalformedPacket("bad probe ack length"));
+                }
+                Ok(Packet::ProbeAck(ProbeAck {
+                    seq: header.object_id,
+                    size: u16::from_be_bytes([body[0], body[1]]),
+                }))
+            }
+            TYPE_HELLO | TYPE_RETRY => {
+                if buf.len() != HELLO_SIZE {
+                    return Err(NyxError::MalformedPacket("bad hello length"));
+                }
+                let nonce = header.object_id;
+                let cookie = body.try_into().expect("checked length");
+                Ok(if header.packet_type == TYPE_HELLO {
+                    Packet::Hello(Hello { nonce, cookie })
+                } else {
+                    Packet::Retry(Retry { nonce, cookie })
+                })
+            }
+            TYPE_HELLO_ACK => {
+                if buf.len() != HELLO_ACK_SIZE {
+                    return Err(NyxError::MalformedPacket("bad hello ack length"));
+                }
+                Ok(Packet::HelloAck(HelloAck {
+                    nonce: header.object_id,
+                }))
+            }
+            TYPE_HANDSHAKE_INIT | TYPE_HANDSHAKE_RESPONSE => {
+                if buf.len() < HANDSHAKE_OVERHEAD
+                    || buf.len() > HANDSHAKE_OVERHEAD + MAX_HANDSHAKE_MESSAGE
+                {
+                    return Err(NyxError::MalformedPacket("bad handshake length"));
+                }
+                let (session_id, message) = body.split_at(HANDSHAKE_OVERHEAD - HEADER_SIZE);
+                let handshake = Handshake {
+                    session_id: u64::from_be_bytes(session_id.try_into().expect("split to size")),
+                    message: message.to_vec(),
+                };
+                Ok(if header.packet_type == TYPE_HANDSHAKE_INIT {
+                    Packet::HandshakeInit(handshake)
+                } else {
+                    Packet::HandshakeResponse(handshake)
+                })
+            }
+            _ => Err(NyxError::MalformedPacket("unknown packet type")),
+        }
+    }
+}
+
+/// Whether a datagram is a hello, judging by its header alone. Hellos are
+/// all a receiver validating addresses accepts from an address it has not
+/// validated yet.
+pub fn is_hello(datagram: &[u8]) -> bool {
+    datagram.get(1) == Some(&TYPE_HELLO)
+}
+
+/// Whether a datagram is a handshake packet, judging by its header alone.
+/// Handshakes are never sealed, so this is how they are told apart from
+/// datagrams that need opening first.
+pub fn is_handshake(datagram: &[u8]) -> bool {
+    matches!(
+        datagram.get(1),
+        Some(&(TYPE_HANDSHAKE_INIT | TYPE_HANDSHAKE_RESPONSE))
+    )
+}
+
+/// CRC32C of a serialized data packet, skipping its checksum field.
+fn symbol_checksum(datagram: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(&datagram[..HEADER_SIZE]);
+    crc32c::crc32c_append(crc, &datagram[COVERED_SIZE..])
+}
+
+/// The digest a [`DataPacket`] carries for `object`.
+pub fn object_digest(object: &[u8]) -> [u8; DIGEST_SIZE] {
+    blake3::hash(object).into()
+}
+
+/// The part of an object's digest that its [`Feedback`] echoes.
+pub fn feedback_digest(digest: &[u8; DIGEST_SIZE]) -> [u8; FEEDBACK_DIGEST_SIZE] {
+    digest[..FEEDBACK_DIGEST_SIZE]
+        .try_into()
+        .expect("digest is longer")
+}
+
+/// Checks that `oti` describes an object raptorq can build a decoder for
+/// without panicking.
+pub fn validate_oti(oti: &ObjectTransmissionInformation) -> Result<()> {
+    let symbol_size = oti.symbol_size();
+    let alignment = oti.symbol_alignment() as u16;
+    if oti.transfer_length() == 0 {
+        return Err(NyxError::MalformedPacket("empty object"));
+    }
+    if symbol_size == 0 || symbol_size > MAX_JUMBO_SYMBOL_SIZE {
+        return Err(NyxError::MalformedPacket("symbol size out of range"));
+    }
+    if symbol_size.checked_rem(alignment) != Some(0) {
+        return Err(NyxError::MalformedPacket(
+            "symbol size not a multiple of alignment",
+        ));
+    }
+    if oti.sub_blocks() == 0 || oti.sub_blocks() > symbol_size / alignment {
+        return Err(NyxError::MalformedPacket("bad sub-block count"));
+    }
+    let total_symbols = oti.transfer_length().div_ceil(symbol_size as u64);
+    let source_blocks = oti.source_blocks() as u64;
+    if source_blocks == 0 || source_blocks > total_symbols {
+        return Err(NyxError::MalformedPacket("bad source block count"));
+    }
+    if total_symbols.div_ceil(source_blocks) > MAX_SOURCE_SYMBOLS_PER_BLOCK {
+        return Err(NyxError::MalformedPacket("too many symbols per block"));
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+// pmtu.rs
+//
+// Datagram packetization layer path MTU discovery, after RFC 8899. Sizes here
+// are UDP payload sizes, i.e. what we hand to `send_to`.
+use std::{io, time::Duration};
+use tokio::{net::UdpSocket, time::Instant};
+
+/// Size assumed to work on any path before probing confirms anything larger.
+pub const BASE_PLPMTU: u16 = 1200;
+/// Probes of one size that may go unanswered before the size counts as failed.
+pub const MAX_PROBES: u8 = 3;
+/// Stop searching once the bounds are this close together.
+const SEARCH_GRANULARITY: u16 = 16;
+/// How long a completed search result is trusted before probing for more.
+const PMTU_RAISE_TIMER: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PmtuState {
+    /// Confirming that the base size gets through.
+    Base,
+    /// Looking for the largest size that gets through.
+    Searching,
+    /// The search has converged on `plpmtu`.
+    SearchComplete,
+    /// Not even the base size was acknowledged; the peer may not answer
+    /// probes at all.
+    Error,
+}
+
+/// The search state machine. It does no I/O: ask it for the next size to
+/// probe and report back what happened.
+#[derive(Clone, Debug)]
+pub struct PmtuSearch {
+    state: PmtuState,
+    base: u16,
+    max: u16,
+    plpmtu: u16,
+    /// Smallest size known not to get through, or `max + 1`.
+    failed: u32,
+    probe: Option<u16>,
+    probe_count: u8,
+    completed_at: Option<Instant>,
+}
+
+impl PmtuSearch {
+    pub fn new(max: u16) -> Self {
+        let base = BASE_PLPMTU.min(max);
+        PmtuSearch {
+            state: PmtuState::Base,
+            base,
+            max,
+            plpmtu: base,
+            failed: max as u32 + 1,
+            probe: None,
+            probe_count: 0,
+            completed_at: None,
+        }
+    }
+
+    pub fn state(&self) -> PmtuState {
+        self.state
+    }
+
+    /// The largest datagram size confirmed (or, before confirmation,
+    /// assumed) to reach the peer.
+    pub fn plpmtu(&self) -> u16 {
+        self.plpmtu
+    }
+
+    /// Whether a search should run before the next transmission.
+    pub fn needs_search(&self) -> bool {
+        match self.state {
+            PmtuState::Base | PmtuState::Searching => true,
+            PmtuState::SearchComplete => match self.completed_at {
+                Some(at) => at.elapsed() >= PMTU_RAISE_TIMER,
+                None => true,
+            },
+            PmtuState::Error => false,
+        }
+    }
+
+    /// The size to probe next, or `None` once the search is over.
+    pub fn next_probe(&mut self) -> Option<u16> {
+        if self.state == PmtuState::SearchComplete && self.needs_search() {
+            // Raise timer expired: the path may carry more than it did.
+            self.state = PmtuState::Searching;
+            self.failed = self.max as u32 + 1;
+            self.probe_count = 0;
+        }
+        let size = match self.state {
+            PmtuState::Base => self.base,
+            PmtuState::Searching if self.failed > self.max as u32 => self.max,
+            PmtuState::Searching => ((self.plpmtu as u32 + self.failed) / 2) as u16,
+            PmtuState::SearchComplete | PmtuState::Error => return None,
+        };
+        self.probe = Some(size);
+        Some(size)
+    }
+
+    /// A probe of `size` bytes was acknowledged.
+    pub fn on_ack(&mut self, size: u16) {
+        if self.probe != Some(size) {
+            return;
+        }
+        self.probe = None;
+        self.probe_count = 0;
+        self.plpmtu = self.plpmtu.max(size);
+        if self.state == PmtuState::Base {
+            self.state = PmtuState::Searching;
+        }
+        self.check_complete();
+    }
+
+    /// The probe in flight went unanswered.
+    pub fn on_timeout(&mut self) {
+        let Some(size) = self.probe else {
+            return;
+        };
+        self.probe_count += 1;
+        if self.probe_count < MAX_PROBES {
+            return;
+        }
+        self.probe_count = 0;
+        self.probe = None;
+        if self.state == PmtuState::Base {
+            self.state = PmtuState::Error;
+        } else {
+            self.failed = size as u32;
+            self.check_complete();
+        }
+    }
+
+    /// The local stack refused to send `size` bytes (`EMSGSIZE`), which is
+    /// as good as a failed probe.
+    pub fn on_too_big(&mut self, size: u16) {
+        self.probe = Some(size);
+        self.probe_count = MAX_PROBES - 1;
+        self.on_timeout();
+    }
+
+    /// Datagrams at the current size have stopped getting through; fall back
+    /// to the base size and search again.
+    pub fn on_black_hole(&mut self) {
+        *self = PmtuSearch::new(self.max);
+    }
+
+    fn check_complete(&mut self) {
+        if self.plpmtu >= self.max || self.failed <= self.plpmtu as u32 + SEARCH_GRANULARITY as u32
+        {
+            self.state = PmtuState::SearchComplete;
+            self.completed_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Sets the Don't Fragment bit on everything sent from `socket`, without
+/// letting the kernel's own PMTU cache reject probes larger than it.
+#[cfg(target_os = "linux")]
+pub fn set_dont_fragment(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name, value) = if socket.local_addr()?.is_ipv4() {
+        (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_PROBE,
+        )
+    } else {
+        (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_PROBE,
+        )
+    };
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_dont_fragment(_socket: &UdpSocket) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether a send failed because the datagram exceeds the local MTU.
+pub fn is_too_big(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EMSGSIZE)
+}
@@ -0,0 +1,313 @@
+// proxy.rs
+//
+// A UDP and UDP-Lite forwarder that impairs what passes through it, so that
+// real clients and servers can be run against a bad link on one machine
+// without root or `tc netem`. Clients send to the proxy; it forwards each
+// client's datagrams to the server from a socket of its own, so that the
+// server's replies come back to the proxy and can be impaired on their way
+// to the client as well. A client silent for long enough is forgotten and
+// its socket closed, and a failure on one client's behalf is reported and
+// leaves the others alone.
+use crate::transport::{
+    self, DatagramTransport, LinkConfig, LinkStats, SimulatedTransport, TransportKind,
+};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::AbortHandle, time::Instant};
+
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// Transports to listen on, all on the same port. Each client's
+    /// datagrams are forwarded over the transport they arrived on.
+    pub transports: Vec<TransportKind>,
+    pub listen: SocketAddr,
+    /// Where the server is.
+    pub upstream: SocketAddr,
+    /// The link from clients to the server. Each client gets a link of its
+    /// own, seeded from this one's seed plus the order the client turned up
+    /// in.
+    pub up: LinkConfig,
+    /// The link from the server to clients, one per transport.
+    pub down: LinkConfig,
+    /// How long a client has to be silent before its socket is closed. It
+    /// gets a new one, and a new link, if it turns up again.
+    pub idle_timeout: Duration,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        ProxyConfig {
+            transports: vec![TransportKind::UdpLite, TransportKind::Udp],
+            listen: "127.0.0.1:55556".parse().expect("valid address"),
+            upstream: "127.0.0.1:55555".parse().expect("valid address"),
+            up: LinkConfig::default(),
+            down: LinkConfig::default(),
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// What the proxy has done to the datagrams passing through it, each way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    pub clients: usize,
+    pub up: LinkStats,
+    pub down: LinkStats,
+}
+
+/// A client the proxy forwards for.
+struct Client {
+    /// The client's link to the server.
+    upstream: Arc<SimulatedTransport>,
+    /// When the client last sent anything.
+    last_active: Instant,
+    /// The task relaying the server's replies back.
+    relay: AbortHandle,
+}
+
+#[derive(Default)]
+struct Clients {
+    links: HashMap<(TransportKind, SocketAddr), Client>,
+    /// Clients seen so far, to seed new clients' links from.
+    seen: u64,
+    /// What the links of clients since forgotten did.
+    forgotten: LinkStats,
+}
+
+impl Clients {
+    fn remove(&mut self, key: (TransportKind, SocketAddr)) {
+        if let Some(client) = self.links.remove(&key) {
+            client.relay.abort();
+            add(&mut self.forgotten, client.upstream.stats());
+        }
+    }
+
+    /// Forgets the clients silent for longer than `idle_timeout`.
+    fn expire(&mut self, idle_timeout: Duration, now: Instant) {
+        let idle: Vec<_> = self
+            .links
+            .iter()
+            .filter(|(_, client)| now.saturating_duration_since(client.last_active) > idle_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in idle {
+            self.remove(key);
+        }
+    }
+}
+
+pub struct Proxy {
+    listeners: Vec<Arc<SimulatedTransport>>,
+    clients: Arc<Mutex<Clients>>,
+    /// The link new clients' datagrams go to the server over.
+    up: Mutex<LinkConfig>,
+    config: ProxyConfig,
+}
+
+impl Proxy {
+    /// Binds each of `config.transports` on `config.listen`. If that has
+    /// port 0, the port picked for the first transport is reused for the
+    /// rest. Transports that fail to bind are left out; binding fails only
+    /// if none of them could be bound.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(mut config: ProxyConfig) -> io::Result<Self> {
+        let mut listeners = Vec::new();
+        let mut last_error = None;
+        for &kind in &config.transports {
+            match transport::bind(kind, config.listen) {
+                Ok(transport) => {
+                    config.listen = transport.local_addr()?;
+                    listeners.push(Arc::new(SimulatedTransport::new(
+                        transport,
+                        config.down.clone(),
+                    )));
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if listeners.is_empty() {
+            return Err(last_error
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no transports")));
+        }
+        Ok(Proxy {
+            listeners,
+            clients: Arc::new(Mutex::new(Clients::default())),
+            up: Mutex::new(config.up.clone()),
+            config,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.config.listen
+    }
+
+    /// The transports the proxy listens on.
+    pub fn transport_kinds(&self) -> Vec<TransportKind> {
+        self.listeners.iter().map(|l| l.kind()).collect()
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        let clients = self.clients.lock().unwrap();
+        let mut stats = ProxyStats {
+            clients: clients.links.len(),
+            up: clients.forgotten,
+            ..ProxyStats::default()
+        };
+        for client in clients.links.values() {
+            add(&mut stats.up, client.upstream.stats());
+        }
+        for link in &self.listeners {
+            add(&mut stats.down, link.stats());
+        }
+        stats
+    }
+
+    /// Changes the links both ways from now on, for clients already seen as
+    /// well as new ones. Links keep their random state, as with
+    /// [`SimulatedTransport::set_config`], so `up.seed` only seeds the links
+    /// of new clients.
+    pub fn set_links(&self, up: LinkConfig, down: LinkConfig) {
+        let clients = self.clients.lock().unwrap();
+        for client in clients.links.values() {
+            client.upstream.set_config(up.clone());
+        }
+        for link in &self.listeners {
+            link.set_config(down.clone());
+        }
+        *self.up.lock().unwrap() = up;
+    }
+
+    /// Forwards datagrams both ways until a listening socket fails.
+    pub async fn run(&self) -> io::Result<()> {
+        futures_util::future::try_join_all(
+            self.listeners
+                .iter()
+                .map(|listener| self.forward(listener.clone())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Forwards what clients send to `listener` on to the server.
+    async fn forward(&self, listener: Arc<SimulatedTransport>) -> io::Result<()> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, client) = listener.recv_from(&mut buf).await?;
+            let upstream = match self.upstream_for(&listener, client) {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    eprintln!("No link to the server for {}: {}", client, e);
+                    continue;
+                }
+            };
+            if let Err(e) = upstream
+                .send_to(&buf[..len.min(buf.len())], self.config.upstream)
+                .await
+            {
+                eprintln!("Forwarding from {} failed: {}", client, e);
+            }
+        }
+    }
+
+    /// The link `client`'s datagrams go to the server over, set up on the
+    /// client's first datagram along with a task relaying the server's
+    /// replies back. Setting one up first forgets idle clients.
+    fn upstream_for(
+        &self,
+        listener: &Arc<SimulatedTransport>,
+        client: SocketAddr,
+    ) -> io::Result<Arc<SimulatedTransport>> {
+        let key = (listener.kind(), client);
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.links.get_mut(&key) {
+            client.last_active = now;
+            return Ok(client.upstream.clone());
+        }
+        clients.expire(self.config.idle_timeout, now);
+        let unspecified = match self.config.upstream.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let up = self.up.lock().unwrap().clone();
+        let up = LinkConfig {
+            seed: up.seed.wrapping_add(clients.seen),
+            ..up
+        };
+        let upstream = Arc::new(SimulatedTransport::new(
+            transport::bind(key.0, SocketAddr::new(unspecified, 0))?,
+            up,
+        ));
+        clients.seen += 1;
+        let relay = tokio::spawn({
+            let upstream = upstream.clone();
+            let server = self.config.upstream;
+            let listener = listener.clone();
+            let clients = self.clients.clone();
+            async move {
+                let e = relay(&upstream, server, &listener, client).await;
+                eprintln!("Relaying replies to {} failed: {}", client, e);
+                // The client gets a new link if it sends again.
+                let mut clients = clients.lock().unwrap();
+                if clients
+                    .links
+                    .get(&key)
+                    .is_some_and(|client| Arc::ptr_eq(&client.upstream, &upstream))
+                {
+                    clients.remove(key);
+                }
+            }
+        });
+        clients.links.insert(
+            key,
+            Client {
+                upstream: upstream.clone(),
+                last_active: now,
+                relay: relay.abort_handle(),
+            },
+        );
+        Ok(upstream)
+    }
+}
+
+/// Relays what `server` sends to `client` until receiving fails, and
+/// returns why. Replies that cannot be sent on to the client are reported
+/// and skipped.
+async fn relay(
+    upstream: &SimulatedTransport,
+    server: SocketAddr,
+    listener: &SimulatedTransport,
+    client: SocketAddr,
+) -> io::Error {
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (len, from) = match upstream.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => return e,
+        };
+        if from != server {
+            continue;
+        }
+        if let Err(e) = listener.send_to(&buf[..len.min(buf.len())], client).await {
+            eprintln!("Relaying a reply to {} failed: {}", client, e);
+        }
+    }
+}
+
+fn add(total: &mut LinkStats, stats: LinkStats) {
+    total.sent += stats.sent;
+    total.bytes += stats.bytes;
+    total.lost += stats.lost;
+    total.overflowed += stats.overflowed;
+    total.reordered += stats.reordered;
+    total.duplicated += stats.duplicated;
+    total.corrupted += stats.corrupted;
+}
@@ -0,0 +1,1346 @@
+// receiver.rs
+use crate::{
+    acl::{Acl, Identity},
+    limits::{decoder_cost, Limits, RateLimiter},
+    validation::{AddressValidator, AMPLIFICATION_LIMIT},
+};
+#[cfg(feature = "crypto")]
+use crate::{
+    crypto::{
+        peek_packet_number, peek_session_id, NoiseResponder, Padding, Psk, RekeyPolicy, Role,
+        SessionKeys, PADDING_TRAILER_SIZE, SEAL_OVERHEAD,
+    },
+    packet::is_handshake,
+};
+use crate::{
+    error::{NyxError, Result},
+    packet::{
+        feedback_digest, is_hello, object_digest, DataPacket, Feedback, Handshake, Hello, HelloAck,
+        Packet, Probe, ProbeAck, Retry, DATA_OVERHEAD, DIGEST_SIZE,
+    },
+    stats::{
+        calculate_symbol_size, NetworkStats, MAX_JUMBO_SYMBOL_SIZE, MAX_SYMBOL_SIZE,
+        MIN_SYMBOL_SIZE,
+    },
+    transport::{self, DatagramTransport, TransportKind},
+};
+use futures_util::future::select_all;
+use raptorq::{Decoder, ObjectTransmissionInformation};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+};
+use tokio::time::Instant;
+
+/// An object decoded from one peer.
+#[derive(Debug)]
+pub struct ReceivedObject {
+    pub peer: SocketAddr,
+    pub object_id: u32,
+    pub data: Vec<u8>,
+    pub packets_received: u32,
+    /// Symbols of this object dropped because their checksum failed.
+    pub packets_corrupted: u32,
+    /// Symbols of this object that never arrived before it was decoded,
+    /// estimated from gaps in the encoding symbol ids.
+    pub packets_lost: u32,
+    /// The BLAKE3 digest `data` was checked against.
+    pub digest: [u8; DIGEST_SIZE],
+}
+
+#[derive(Clone, Debug)]
+pub struct ReceiverConfig {
+    /// Transports [`Receiver::bind_with_config`] listens on, all on the same
+    /// port, so that senders can fall back from one to another.
+    pub transports: Vec<TransportKind>,
+    /// Largest symbol the receiver accepts and asks senders for. Receive
+    /// buffers are sized from it, so symbols up to
+    /// [`MAX_JUMBO_SYMBOL_SIZE`] need it raised explicitly.
+    pub max_symbol_size: u16,
+    /// Ignore everything but hellos from an address until a hello from it
+    /// has echoed a cookie sent to it, so that senders with spoofed
+    /// addresses get no decoder state and no replies beyond the cookie.
+    pub validate_addresses: bool,
+    /// Replies to addresses not yet validated are at most this many times
+    /// larger than the datagram they answer; larger ones are not sent.
+    pub amplification_limit: usize,
+    /// Bounds on the sessions, decoders and packet rates senders may use.
+    pub limits: Limits,
+    /// Which addresses and identities may send, and limits for particular
+    /// identities. Addresses are checked before anything else is done with
+    /// a datagram, identities as soon as a session authenticates.
+    pub acl: Acl,
+    /// Accept only datagrams sealed with keys derived from this pre-shared
+    /// key, and seal replies likewise.
+    #[cfg(feature = "crypto")]
+    pub psk: Option<Psk>,
+    /// Further pre-shared keys, each naming the identity of the senders
+    /// holding it, for the access list to tell apart. Not used with Noise.
+    #[cfg(feature = "crypto")]
+    pub psk_identities: Vec<(String, Psk)>,
+    /// Take part in Noise handshakes with this identity, and accept only
+    /// datagrams sealed with the keys they establish. If `psk` is set as
+    /// well, handshakes also require it, and it no longer keys sessions by
+    /// itself.
+    #[cfg(feature = "crypto")]
+    pub noise: Option<NoiseResponder>,
+    /// When session keys are rotated.
+    #[cfg(feature = "crypto")]
+    pub rekey: RekeyPolicy,
+    /// Pad sealed replies, all of them control packets, to a constant size.
+    #[cfg(feature = "crypto")]
+    pub padding: Option<Padding>,
+}
+
+#[cfg(feature = "crypto")]
+impl ReceiverConfig {
+    /// Whether datagrams other than handshakes must arrive sealed.
+    fn sealed(&self) -> bool {
+        self.psk.is_some() || !self.psk_identities.is_empty() || self.noise.is_some()
+    }
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        ReceiverConfig {
+            transports: vec![TransportKind::UdpLite, TransportKind::Udp],
+            max_symbol_size: MAX_SYMBOL_SIZE,
+            validate_addresses: true,
+            amplification_limit: AMPLIFICATION_LIMIT,
+            limits: Limits::default(),
+            acl: Acl::default(),
+            #[cfg(feature = "crypto")]
+            psk: None,
+            #[cfg(feature = "crypto")]
+            psk_identities: Vec::new(),
+            #[cfg(feature = "crypto")]
+            noise: None,
+            #[cfg(feature = "crypto")]
+            rekey: RekeyPolicy::default(),
+            #[cfg(feature = "crypto")]
+            padding: None,
+        }
+    }
+}
+
+/// What a datagram handed to the [`Reassembler`] amounted to.
+#[derive(Debug)]
+pub enum ReceiveEvent {
+    /// The datagram completed an object.
+    Object(ReceivedObject),
+    /// A path MTU probe that should be acknowledged.
+    Probe(Probe),
+    /// A sender opening a session, to be acknowledged.
+    Hello(Hello),
+    /// A hello from an address that has yet to be validated, to be answered
+    /// with this retry.
+    Retry(Retry),
+    /// A sender starting a key exchange, to be answered if the receiver
+    /// takes part in them.
+    Handshake(Handshake),
+    /// An object decoded from `peer` did not match the digest its sender
+    /// announced. It is decoded afresh from the symbols still to come.
+    IntegrityFailure { peer: SocketAddr, object_id: u32 },
+}
+
+/// Counters for datagrams the receiver has seen, including ones it dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceiverStats {
+    pub datagrams_received: u64,
+    pub objects_decoded: u64,
+    /// Datagrams that failed to parse or validate.
+    pub malformed_packets: u64,
+    /// Data packets whose symbol checksum failed, treated as erasures.
+    pub corrupted_packets: u64,
+    /// Datagrams that failed authentication under a pre-shared key. With
+    /// sealing on, damaged symbols are counted here rather than as
+    /// corrupted, since the two look the same.
+    pub auth_failures: u64,
+    /// Sealed datagrams that authenticated but had been received before,
+    /// or were too old to tell.
+    pub replayed_packets: u64,
+    /// Symbols missing from decoded objects, as in
+    /// [`ReceivedObject::packets_lost`].
+    pub lost_packets: u64,
+    /// Datagrams larger than the receive buffer.
+    pub truncated_packets: u64,
+    /// Symbols larger than the configured maximum symbol size.
+    pub oversized_packets: u64,
+    pub version_mismatches: u64,
+    /// Symbols whose OTI disagrees with earlier symbols of the same object.
+    pub inconsistent_packets: u64,
+    /// Objects whose decoded bytes did not match their digest.
+    pub integrity_failures: u64,
+    /// Datagrams other than hellos from addresses not yet validated, or
+    /// from sessions keyed by a pre-shared key that have yet to show they
+    /// are not replayed.
+    pub unvalidated_packets: u64,
+    /// Replies not sent because they would have exceeded the amplification
+    /// limit for an address not yet validated.
+    pub withheld_replies: u64,
+    /// Replies, feedback included, that the transport failed to send. The
+    /// peer is left to resend; the receiver carries on.
+    pub failed_replies: u64,
+    /// Datagrams from addresses, or sessions of identities, refused by the
+    /// access list.
+    pub denied_packets: u64,
+    /// Datagrams from new peers dropped because the receiver already held
+    /// as many sessions as it allows, overall or for the peer's identity.
+    pub session_limited_packets: u64,
+    /// Datagrams beyond a peer's packet rate.
+    pub rate_limited_packets: u64,
+    /// Symbols of new objects dropped because their decoders would have
+    /// exceeded the decoder memory budget, overall or for the peer's
+    /// identity, or because the peer may not have any objects decoding.
+    pub over_budget_packets: u64,
+    /// Objects abandoned half-decoded to make way for newer ones from the
+    /// same peer.
+    pub evicted_objects: u64,
+    /// Sessions of idle peers dropped to make way for others.
+    pub evicted_sessions: u64,
+    /// Well-formed packets of a type the receiver does not accept.
+    pub unexpected_packets: u64,
+    /// Bytes of padding in sealed datagrams received, and in replies sent,
+    /// counted unsealed.
+    pub padding_bytes_received: u64,
+    pub padding_bytes_sent: u64,
+    pub probes_received: u64,
+    pub hellos_received: u64,
+    pub handshakes_received: u64,
+    /// Handshakes refused because they did not authenticate or came from a
+    /// sender that is not authorized.
+    pub handshake_failures: u64,
+}
+
+/// An object being decoded.
+struct PendingObject {
+    object_id: u32,
+    oti: ObjectTransmissionInformation,
+    digest: [u8; DIGEST_SIZE],
+    decoder: Decoder,
+    packets_received: u32,
+    /// Per source block, the encoding symbol id expected next. Senders emit
+    /// them in order, so skipping ahead means symbols went missing.
+    next_esi: Vec<u32>,
+    missing: u32,
+    /// Decoder memory charged for the object, as estimated by
+    /// [`decoder_cost`].
+    cost: u64,
+    last_active: Instant,
+}
+
+impl PendingObject {
+    fn new(data: &DataPacket, cost: u64, now: Instant) -> Self {
+        let oti = data.oti;
+        PendingObject {
+            object_id: data.object_id,
+            oti,
+            digest: data.digest,
+            decoder: Decoder::new(oti),
+            packets_received: 0,
+            next_esi: vec![0; oti.source_blocks() as usize],
+            missing: 0,
+            cost,
+            last_active: now,
+        }
+    }
+
+    /// Starts decoding the same object over.
+    fn restart(&mut self) {
+        self.decoder = Decoder::new(self.oti);
+        self.packets_received = 0;
+        self.next_esi.fill(0);
+        self.missing = 0;
+    }
+
+    fn track_esi(&mut self, block: u8, esi: u32) {
+        let next = &mut self.next_esi[block as usize];
+        if esi >= *next {
+            self.missing += esi - *next;
+            *next = esi + 1;
+        } else {
+            // A late arrival filling a gap counted earlier.
+            self.missing = self.missing.saturating_sub(1);
+        }
+    }
+}
+
+/// How many delivered objects per peer are remembered, so that their
+/// surplus symbols are recognized and ignored.
+const COMPLETED_HISTORY: usize = 8;
+
+/// What the receiver holds for one peer.
+struct Session {
+    /// Who the peer authenticated as, if anyone.
+    identity: Option<Identity>,
+    max_objects: usize,
+    objects: Vec<PendingObject>,
+    completed: VecDeque<u32>,
+    /// The object the peer's latest corrupted symbol belonged to and how
+    /// many of that object's symbols were corrupted. Kept apart from the
+    /// pending objects because corruption can hit an object before any of
+    /// its symbols has arrived intact.
+    corrupted: Option<(u32, u32)>,
+    rate: RateLimiter,
+    last_active: Instant,
+}
+
+impl Session {
+    fn new(identity: Option<Identity>, limits: &Limits, now: Instant) -> Self {
+        Session {
+            identity,
+            max_objects: limits.max_objects_per_session,
+            objects: Vec::new(),
+            completed: VecDeque::new(),
+            corrupted: None,
+            rate: RateLimiter::new(limits.max_packets_per_second, now),
+            last_active: now,
+        }
+    }
+
+    fn decoder_memory(&self) -> u64 {
+        self.objects.iter().map(|object| object.cost).sum()
+    }
+}
+
+/// The socket-free half of the receiver: parses and validates datagrams and
+/// feeds them to per-peer decoders, within the bounds set by its [`Limits`]
+/// and access list.
+pub struct Reassembler {
+    sessions: HashMap<SocketAddr, Session>,
+    stats: ReceiverStats,
+    max_symbol_size: u16,
+    limits: Limits,
+    acl: Acl,
+    /// Sum of the costs of all pending objects.
+    decoder_memory: u64,
+    validator: Option<AddressValidator>,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self::with_max_symbol_size(MAX_SYMBOL_SIZE)
+    }
+
+    pub fn with_max_symbol_size(max_symbol_size: u16) -> Self {
+        Self::with_limits(max_symbol_size, Limits::default())
+    }
+
+    pub fn with_limits(max_symbol_size: u16, limits: Limits) -> Self {
+        Reassembler {
+            sessions: HashMap::new(),
+            stats: ReceiverStats::default(),
+            max_symbol_size,
+            limits,
+            acl: Acl::default(),
+            decoder_memory: 0,
+            validator: None,
+        }
+    }
+
+    /// Turns on address validation: from then on, datagrams other than
+    /// hellos are dropped unless their sender has echoed a cookie, and
+    /// hellos without a valid one are answered with a [`ReceiveEvent::Retry`].
+    pub fn require_address_validation(&mut self) {
+        self.validator.get_or_insert_with(AddressValidator::new);
+    }
+
+    /// Applies an access list: from then on, datagrams from addresses it
+    /// refuses are dropped, and sessions of identities it sets limits for
+    /// are held to those.
+    pub fn restrict(&mut self, acl: Acl) {
+        self.acl = acl;
+    }
+
+    /// Whether `peer` has validated its address, or need not.
+    pub fn is_validated(&self, peer: SocketAddr) -> bool {
+        self.validator
+            .as_ref()
+            .is_none_or(|validator| validator.is_validated(peer))
+    }
+
+    /// Counts a datagram that was cut short by the receive buffer.
+    pub fn handle_truncated(&mut self) {
+        self.stats.datagrams_received += 1;
+        self.stats.truncated_packets += 1;
+    }
+
+    /// Whether the receiver holds a session for `peer`.
+    pub fn has_session(&self, peer: SocketAddr) -> bool {
+        self.sessions.contains_key(&peer)
+    }
+
+    /// Forgets `peer`'s session, objects delivered included, for when the
+    /// peer starts over under new keys.
+    pub fn end_session(&mut self, peer: SocketAddr) {
+        self.remove_session(peer);
+    }
+
+    /// Counts a datagram of a session that has yet to show it is not a
+    /// replay, answered with a retry if it is a hello and dropped if not.
+    pub fn handle_unproven(&mut self, hello: bool) {
+        self.stats.datagrams_received += 1;
+        if hello {
+            self.stats.hellos_received += 1;
+        } else {
+            self.stats.unvalidated_packets += 1;
+        }
+    }
+
+    /// Counts a datagram that failed authentication before it got here.
+    pub fn handle_auth_failure(&mut self) {
+        self.stats.datagrams_received += 1;
+        self.stats.auth_failures += 1;
+    }
+
+    /// Counts an authenticated datagram that was dropped as a replay before
+    /// it got here.
+    pub fn handle_replay(&mut self) {
+        self.stats.datagrams_received += 1;
+        self.stats.replayed_packets += 1;
+    }
+
+    /// Counts a datagram dropped before it got here for want of room to
+    /// hold keys for another peer.
+    pub fn handle_session_limited(&mut self) {
+        self.stats.datagrams_received += 1;
+        self.stats.session_limited_packets += 1;
+    }
+
+    /// Counts a datagram refused by the access list before it got here.
+    pub fn handle_denied(&mut self) {
+        self.stats.datagrams_received += 1;
+        self.stats.denied_packets += 1;
+    }
+
+    /// Counts padding stripped from a datagram before it got here.
+    pub fn handle_padding_received(&mut self, bytes: usize) {
+        self.stats.padding_bytes_received += bytes as u64;
+    }
+
+    /// Counts padding added to a reply.
+    pub fn handle_padding_sent(&mut self, bytes: usize) {
+        self.stats.padding_bytes_sent += bytes as u64;
+    }
+
+    /// Counts a reply withheld by the amplification limit.
+    pub fn handle_withheld_reply(&mut self) {
+        self.stats.withheld_replies += 1;
+    }
+
+    /// Counts a reply the transport failed to send.
+    pub fn handle_failed_reply(&mut self) {
+        self.stats.failed_replies += 1;
+    }
+
+    /// Counts a handshake, already returned by
+    /// [`handle_datagram`](Self::handle_datagram), that was refused.
+    pub fn handle_handshake_failure(&mut self) {
+        self.stats.handshake_failures += 1;
+    }
+
+    pub fn stats(&self) -> ReceiverStats {
+        self.stats
+    }
+
+    /// Handles one datagram from `peer`, returning the object it completes
+    /// or the probe, hello or handshake it carries, if any. With address
+    /// validation on, a hello may instead call for a retry. Invalid
+    /// datagrams, and datagrams refused by the access list or beyond the
+    /// receiver's limits, are counted and dropped.
+    pub fn handle_datagram(&mut self, peer: SocketAddr, datagram: &[u8]) -> Option<ReceiveEvent> {
+        self.handle_identified(peer, None, datagram)
+    }
+
+    /// Handles one datagram, like [`handle_datagram`](Self::handle_datagram),
+    /// from a session that authenticated as `identity`. The peer's session
+    /// is held to the limits the access list sets for that identity.
+    pub fn handle_identified(
+        &mut self,
+        peer: SocketAddr,
+        identity: Option<&Identity>,
+        datagram: &[u8],
+    ) -> Option<ReceiveEvent> {
+        self.stats.datagrams_received += 1;
+        if !self.acl.permits_address(peer) {
+            self.stats.denied_packets += 1;
+            return None;
+        }
+        let now = Instant::now();
+        if self.is_validated(peer) {
+            if !self.admit(peer, identity, now) {
+                return None;
+            }
+        } else if !is_hello(datagram) {
+            self.stats.unvalidated_packets += 1;
+            return None;
+        }
+        let data = match Packet::parse(datagram) {
+            Ok(Packet::Data(data)) => data,
+            Ok(Packet::Probe(probe)) => {
+                self.stats.probes_received += 1;
+                return Some(ReceiveEvent::Probe(probe));
+            }
+            Ok(Packet::Hello(hello)) => {
+                self.stats.hellos_received += 1;
+                if let Some(validator) = &self.validator {
+                    if !validator.is_validated(peer) {
+                        if !validator.check(peer, &hello.cookie) {
+                            return Some(ReceiveEvent::Retry(Retry {
+                                nonce: hello.nonce,
+                                cookie: validator.cookie(peer),
+                            }));
+                        }
+                        // A validated address gets a session, so that it
+                        // counts against the session limit and is
+                        // forgotten with the session.
+                        if !self.admit(peer, identity, now) {
+                            return None;
+                        }
+                        if let Some(validator) = &mut self.validator {
+                            validator.validate(peer);
+                        }
+                    }
+                }
+                return Some(ReceiveEvent::Hello(hello));
+            }
+            Ok(Packet::HandshakeInit(init)) => {
+                self.stats.handshakes_received += 1;
+                return Some(ReceiveEvent::Handshake(init));
+            }
+            Ok(
+                Packet::Feedback(_)
+                | Packet::ProbeAck(_)
+                | Packet::HelloAck(_)
+                | Packet::Retry(_)
+                | Packet::HandshakeResponse(_),
+            ) => {
+                self.stats.unexpected_packets += 1;
+                return None;
+            }
+            Err(NyxError::ChecksumMismatch { object_id }) => {
+                self.stats.corrupted_packets += 1;
+                if let Some(session) = self.sessions.get_mut(&peer) {
+                    let corrupted = session.corrupted.get_or_insert((object_id, 0));
+                    if corrupted.0 != object_id {
+                        *corrupted = (object_id, 0);
+                    }
+                    corrupted.1 += 1;
+                }
+                return None;
+            }
+            Err(NyxError::VersionMismatch { .. }) => {
+                self.stats.version_mismatches += 1;
+                return None;
+            }
+            Err(_) => {
+                self.stats.malformed_packets += 1;
+                return None;
+            }
+        };
+        if data.oti.symbol_size() > self.max_symbol_size {
+            self.stats.oversized_packets += 1;
+            return None;
+        }
+        self.handle_data(peer, data, now)
+    }
+
+    /// Makes sure `peer` has a session and takes a packet from its rate
+    /// limit, evicting idle sessions if the session limit is reached.
+    /// Returns false, having counted why, if the packet is to be dropped.
+    fn admit(&mut self, peer: SocketAddr, identity: Option<&Identity>, now: Instant) -> bool {
+        if self
+            .sessions
+            .get(&peer)
+            .is_some_and(|session| session.identity.as_ref() != identity)
+        {
+            // The peer authenticated afresh as someone else, who is not
+            // answerable for what it had pending.
+            self.remove_session(peer);
+        }
+        if !self.sessions.contains_key(&peer) {
+            if !self.has_room_for_session(identity) {
+                self.evict_idle(now);
+            }
+            if !self.has_room_for_session(identity) {
+                self.stats.session_limited_packets += 1;
+                return false;
+            }
+            let limits = identity
+                .and_then(|identity| self.acl.limits_for(identity))
+                .unwrap_or(&self.limits);
+            self.sessions
+                .insert(peer, Session::new(identity.cloned(), limits, now));
+        }
+        let session = self.sessions.get_mut(&peer).expect("inserted above");
+        if !session.rate.allow(now) {
+            self.stats.rate_limited_packets += 1;
+            return false;
+        }
+        session.last_active = now;
+        true
+    }
+
+    /// Whether another session fits, overall and for `identity`.
+    fn has_room_for_session(&self, identity: Option<&Identity>) -> bool {
+        if self.sessions.len() >= self.limits.max_sessions {
+            return false;
+        }
+        let Some((identity, limits)) =
+            identity.and_then(|identity| Some((identity, self.acl.limits_for(identity)?)))
+        else {
+            return true;
+        };
+        self.sessions
+            .values()
+            .filter(|session| session.identity.as_ref() == Some(identity))
+            .count()
+            < limits.max_sessions
+    }
+
+    fn remove_session(&mut self, peer: SocketAddr) {
+        if let Some(session) = self.sessions.remove(&peer) {
+            self.decoder_memory -= session.decoder_memory();
+        }
+    }
+
+    /// Drops the sessions of peers silent for longer than the idle timeout,
+    /// releasing their decoders. Their addresses have to be validated anew.
+    fn evict_idle(&mut self, now: Instant) {
+        let idle_timeout = self.limits.idle_timeout;
+        let mut released = 0;
+        let mut evicted = Vec::new();
+        self.sessions.retain(|peer, session| {
+            let idle = now.saturating_duration_since(session.last_active) > idle_timeout;
+            if idle {
+                released += session.decoder_memory();
+                self.stats.evicted_sessions += 1;
+                evicted.push(*peer);
+            }
+            !idle
+        });
+        self.decoder_memory -= released;
+        if let Some(validator) = &mut self.validator {
+            for peer in evicted {
+                validator.forget(peer);
+            }
+        }
+    }
+
+    /// Starts decoding a new object from `peer`, making way for it within
+    /// the per-session and memory limits. Returns its index among the
+    /// peer's pending objects, or None if it does not fit.
+    fn start_object(&mut self, peer: SocketAddr, data: &DataPacket, now: Instant) -> Option<usize> {
+        let cost = decoder_cost(&data.oti);
+        let session = self.sessions.get(&peer)?;
+        if session.max_objects == 0 {
+            self.stats.over_budget_packets += 1;
+            return None;
+        }
+        // The peer's least recently active object, if this one is to take
+        // its place. Its memory counts as free for the budgets, but it is
+        // only evicted once the new object is sure to be admitted.
+        let oldest = (session.objects.len() >= session.max_objects)
+            .then(|| {
+                session
+                    .objects
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, object)| object.last_active)
+                    .map(|(index, object)| (index, object.cost))
+            })
+            .flatten();
+        let freed = oldest.map_or(0, |(_, cost)| cost);
+        let identity_budget = session.identity.as_ref().and_then(|identity| {
+            let limits = self.acl.limits_for(identity)?;
+            Some((identity.clone(), limits.max_decoder_memory))
+        });
+        let fits = |used: u64, budget: u64| used - freed + cost <= budget;
+        if !fits(self.decoder_memory, self.limits.max_decoder_memory) {
+            self.evict_idle(now);
+            if !fits(self.decoder_memory, self.limits.max_decoder_memory) {
+                self.stats.over_budget_packets += 1;
+                return None;
+            }
+        }
+        if let Some((identity, budget)) = identity_budget {
+            let used: u64 = self
+                .sessions
+                .values()
+                .filter(|session| session.identity.as_ref() == Some(&identity))
+                .map(Session::decoder_memory)
+                .sum();
+            if !fits(used, budget) {
+                self.stats.over_budget_packets += 1;
+                return None;
+            }
+        }
+        let session = self.sessions.get_mut(&peer)?;
+        if let Some((oldest, _)) = oldest {
+            let evicted = session.objects.swap_remove(oldest);
+            self.decoder_memory -= evicted.cost;
+            self.stats.evicted_objects += 1;
+        }
+        self.decoder_memory += cost;
+        session.objects.push(PendingObject::new(data, cost, now));
+        Some(session.objects.len() - 1)
+    }
+
+    fn handle_data(
+        &mut self,
+        peer: SocketAddr,
+        data: DataPacket,
+        now: Instant,
+    ) -> Option<ReceiveEvent> {
+        let session = self.sessions.get(&peer)?;
+        if session.completed.contains(&data.object_id) {
+            // Surplus symbols of an object we already delivered.
+            return None;
+        }
+        let index = match session
+            .objects
+            .iter()
+            .position(|object| object.object_id == data.object_id)
+        {
+            Some(index) => index,
+            None => self.start_object(peer, &data, now)?,
+        };
+        let session = self.sessions.get_mut(&peer)?;
+        let object = &mut session.objects[index];
+        if object.oti != data.oti || object.digest != data.digest {
+            self.stats.inconsistent_packets += 1;
+            return None;
+        }
+        object.packets_received += 1;
+        object.last_active = now;
+        let payload_id = data.packet.payload_id();
+        object.track_esi(
+            payload_id.source_block_number(),
+            payload_id.encoding_symbol_id(),
+        );
+
+        let decoded = object.decoder.decode(data.packet)?;
+        if object_digest(&decoded) != object.digest {
+            self.stats.integrity_failures += 1;
+            object.restart();
+            return Some(ReceiveEvent::IntegrityFailure {
+                peer,
+                object_id: data.object_id,
+            });
+        }
+        let object = session.objects.swap_remove(index);
+        self.decoder_memory -= object.cost;
+        if session.completed.len() == COMPLETED_HISTORY {
+            session.completed.pop_front();
+        }
+        session.completed.push_back(object.object_id);
+        let packets_corrupted = match session.corrupted.take() {
+            Some((object_id, count)) if object_id == data.object_id => count,
+            _ => 0,
+        };
+        let object = ReceivedObject {
+            peer,
+            object_id: data.object_id,
+            data: decoded,
+            packets_received: object.packets_received,
+            packets_corrupted,
+            packets_lost: object.missing.saturating_sub(packets_corrupted),
+            digest: object.digest,
+        };
+        self.stats.objects_decoded += 1;
+        self.stats.lost_packets += object.packets_lost as u64;
+        Some(ReceiveEvent::Object(object))
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receives objects from any number of senders and answers each decoded
+/// object with feedback carrying the symbol size to use next.
+///
+/// A receiver may listen on several transports at once; every reply goes
+/// out over the transport its request came in on.
+pub struct Receiver {
+    transports: Vec<Box<dyn DatagramTransport>>,
+    network_stats: NetworkStats,
+    reassembler: Reassembler,
+    config: ReceiverConfig,
+    bufs: Vec<Vec<u8>>,
+    /// The sessions each peer has authenticated, held for no more peers
+    /// than `Limits::max_sessions` and dropped with the peer's session.
+    #[cfg(feature = "crypto")]
+    keys: HashMap<SocketAddr, PeerKeys>,
+    /// Mints the cookies new sessions keyed by a pre-shared key echo to
+    /// show they are not recordings of old ones.
+    #[cfg(feature = "crypto")]
+    session_cookies: AddressValidator,
+}
+
+/// The sessions the receiver holds keys for on behalf of one peer.
+#[cfg(feature = "crypto")]
+#[derive(Default)]
+struct PeerKeys {
+    /// The session the peer has shown to be live, which replies are sealed
+    /// under.
+    live: Option<SessionKeys>,
+    /// A session the peer has started but not yet shown to be live. A
+    /// handshake can be replayed, so this does not replace `live` until a
+    /// datagram sealed under it arrives. Datagrams sealed under a PSK can
+    /// be replayed too, so a session keyed by one alone must also echo a
+    /// cookie bound to it.
+    pending: Option<SessionKeys>,
+    /// Ids of the peer's sessions that `live` replaced, most recent last,
+    /// whose datagrams are replays.
+    retired: VecDeque<u64>,
+    /// The latest handshake response sent to the peer, sent again if the
+    /// sender retransmits its handshake because the response was lost.
+    handshake: Option<Handshake>,
+}
+
+/// How many replaced sessions per peer are remembered.
+#[cfg(feature = "crypto")]
+const RETIRED_SESSIONS: usize = 8;
+
+#[cfg(feature = "crypto")]
+impl PeerKeys {
+    /// Makes the pending session the live one, retiring the one it
+    /// replaces.
+    fn promote(&mut self) {
+        if let Some(live) = self.live.take() {
+            if self.retired.len() == RETIRED_SESSIONS {
+                self.retired.pop_front();
+            }
+            self.retired.push_back(live.session_id());
+        }
+        self.live = self.pending.take();
+    }
+
+    fn is_retired(&self, session_id: u64) -> bool {
+        self.retired.contains(&session_id)
+    }
+}
+
+/// What to do with a datagram of a session keyed by a pre-shared key alone
+/// that has yet to show it is not a replay.
+#[cfg(feature = "crypto")]
+enum Challenge {
+    /// The session has shown it, and the datagram is handled as usual.
+    Passed,
+    /// Send back this retry, carrying a cookie bound to the session.
+    Retry(Vec<u8>),
+    /// Drop the datagram.
+    Dropped,
+}
+
+impl Receiver {
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        Self::bind_with_config(addr, ReceiverConfig::default()).await
+    }
+
+    /// Binds each of `config.transports` on `addr`. If `addr` has port 0,
+    /// the port picked for the first transport is reused for the rest.
+    ///
+    /// Transports that fail to bind (say, UDP-Lite on a kernel without it)
+    /// are left out; binding fails only if none of them could be bound.
+    pub async fn bind_with_config(mut addr: SocketAddr, config: ReceiverConfig) -> Result<Self> {
+        let mut transports = Vec::new();
+        let mut last_error = None;
+        for &kind in &config.transports {
+            match transport::bind(kind, addr) {
+                Ok(transport) => {
+                    addr = transport.local_addr()?;
+                    transports.push(transport);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if transports.is_empty() {
+            return Err(last_error
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no transports"))
+                .into());
+        }
+        Self::with_transports(transports, config)
+    }
+
+    /// Receives over an already bound transport. `config.transports` is
+    /// ignored.
+    pub fn with_transport(transport: Box<dyn DatagramTransport>, config: ReceiverConfig) -> Self {
+        Self::with_transports(vec![transport], config).expect("one transport")
+    }
+
+    /// Receives over several already bound transports at once.
+    /// `config.transports` is ignored.
+    pub fn with_transports(
+        transports: Vec<Box<dyn DatagramTransport>>,
+        mut config: ReceiverConfig,
+    ) -> Result<Self> {
+        if transports.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no transports").into());
+        }
+        config.max_symbol_size = config
+            .max_symbol_size
+            .clamp(MIN_SYMBOL_SIZE, MAX_JUMBO_SYMBOL_SIZE);
+        #[allow(unused_mut)]
+        let mut buf_size = config.max_symbol_size as usize + DATA_OVERHEAD;
+        #[cfg(feature = "crypto")]
+        if config.sealed() {
+            // Senders padding their datagrams add a trailer.
+            buf_size += SEAL_OVERHEAD + PADDING_TRAILER_SIZE;
+        }
+        let mut reassembler =
+            Reassembler::with_limits(config.max_symbol_size, config.limits.clone());
+        if config.validate_addresses {
+            reassembler.require_address_validation();
+        }
+        reassembler.restrict(config.acl.clone());
+        Ok(Receiver {
+            bufs: transports.iter().map(|_| vec![0u8; buf_size]).collect(),
+            transports,
+            network_stats: NetworkStats::new(),
+            reassembler,
+            config,
+            #[cfg(feature = "crypto")]
+            keys: HashMap::new(),
+            #[cfg(feature = "crypto")]
+            session_cookies: AddressValidator::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.transports[0].local_addr()?)
+    }
+
+    /// The transports this receiver listens on.
+    pub fn transport_kinds(&self) -> Vec<TransportKind> {
+        self.transports.iter().map(|t| t.kind()).collect()
+    }
+
+    pub fn network_quality(&self) -> f64 {
+        self.network_stats.get_network_quality()
+    }
+
+    /// The symbol size senders are asked to use for their next object.
+    pub fn next_symbol_size(&self) -> u16 {
+        calculate_symbol_size(self.network_quality(), self.config.max_symbol_size)
+    }
+
+    pub fn stats(&self) -> ReceiverStats {
+        self.reassembler.stats()
+    }
+
+    /// Receives datagrams until an object has been decoded, then sends its
+    /// feedback and returns it. Probes, hellos and handshakes are answered
+    /// along the way, and hellos from addresses not yet validated get a
+    /// retry.
+    ///
+    /// Invalid datagrams are counted in [`ReceiverStats`] and dropped, as
+    /// are replies that fail to send, so that one unreachable peer does not
+    /// hold up the others. Errors are returned for receive failures and for
+    /// objects that decode to something other than what was sent
+    /// ([`NyxError::IntegrityFailure`]); the receiver stays usable after
+    /// the latter.
+    pub async fn recv_object(&mut self) -> Result<ReceivedObject> {
+        loop {
+            let start_time = Instant::now();
+            let recvs = self
+                .transports
+                .iter()
+                .zip(self.bufs.iter_mut())
+                .map(|(transport, buf)| transport.recv_from(buf));
+            let (received, index, _) = select_all(recvs).await;
+            let (size, src_addr) = match received {
+                Ok(received) => received,
+                Err(e) => {
+                    self.network_stats.update(false, None);
+                    return Err(e.into());
+                }
+            };
+            self.network_stats
+                .update(true, Some(start_time.elapsed().as_millis()));
+
+            if size > self.bufs[index].len() {
+                self.reassembler.handle_truncated();
+                continue;
+            }
+            if !self.config.acl.permits_address(src_addr) {
+                self.reassembler.handle_denied();
+                continue;
+            }
+            let datagram = &self.bufs[index][..size];
+            // Handshakes travel in the clear, and so do hellos under Noise,
+            // which come before there are any keys.
+            #[cfg(feature = "crypto")]
+            let clear = is_handshake(datagram) || self.config.noise.is_some() && is_hello(datagram);
+            #[cfg(feature = "crypto")]
+            let live = self.live_session_id(src_addr);
+            #[cfg(feature = "crypto")]
+            let opened = if self.config.sealed() && !clear {
+                match open_datagram(
+                    &mut self.keys,
+                    &self.reassembler,
+                    &self.config,
+                    src_addr,
+                    datagram,
+                ) {
+                    Ok((opened, proven)) => {
+                        let padding = datagram.len() - SEAL_OVERHEAD - opened.len();
+                        self.reassembler.handle_padding_received(padding);
+                        if !proven {
+                            match challenge(
+                                &mut self.keys,
+                                &self.session_cookies,
+                                &mut self.reassembler,
+                                src_addr,
+                                &opened,
+                            ) {
+                                Challenge::Passed => {}
+                                Challenge::Retry(retry) => {
+                                    if self.reassembler.is_validated(src_addr)
+                                        || retry.len()
+                                            <= self.config.amplification_limit.saturating_mul(size)
+                                    {
+                                        self.reply(index, &retry, src_addr).await;
+                                    } else {
+                                        self.reassembler.handle_withheld_reply();
+                                    }
+                                    continue;
+                                }
+                                Challenge::Dropped => continue,
+                            }
+                        }
+                        Some(opened)
+                    }
+                    Err(NyxError::Replayed { .. }) => {
+                        self.reassembler.handle_replay();
+                        continue;
+                    }
+                    Err(NyxError::Denied) => {
+                        self.reassembler.handle_denied();
+                        continue;
+                    }
+                    Err(NyxError::ResourceLimit(_)) => {
+                        self.reassembler.handle_session_limited();
+                        continue;
+                    }
+                    Err(_) => {
+                        self.reassembler.handle_auth_failure();
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            // Replies are sealed if and only if what they answer was.
+            #[cfg(feature = "crypto")]
+            let sealed = opened.is_some();
+            #[cfg(not(feature = "crypto"))]
+            let sealed = false;
+            #[cfg(feature = "crypto")]
+            let datagram = opened.as_deref().unwrap_or(datagram);
+            // A peer that has moved on to a new session starts over, and
+            // may reuse the object ids of the old one.
+            #[cfg(feature = "crypto")]
+            if live.is_some() && self.live_session_id(src_addr) != live {
+                self.reassembler.end_session(src_addr);
+            }
+
+            #[cfg(feature = "crypto")]
+            let identity = self
+                .keys
+                .get(&src_addr)
+                .filter(|_| sealed)
+                .and_then(|keys| keys.live.as_ref()?.identity());
+            #[cfg(not(feature = "crypto"))]
+            let identity = None;
+            #[cfg(feature = "crypto")]
+            let evicted = self.reassembler.stats().evicted_sessions;
+            let event = self
+                .reassembler
+                .handle_identified(src_addr, identity, datagram);
+            // Keys are held no longer than the sessions they serve.
+            #[cfg(feature = "crypto")]
+            if self.reassembler.stats().evicted_sessions != evicted {
+                let reassembler = &self.reassembler;
+                self.keys
+                    .retain(|peer, keys| keys.live.is_none() || reassembler.has_session(*peer));
+            }
+            let reply = match event {
+                Some(ReceiveEvent::Object(object)) => {
+                    let feedback = self.encode(
+                        src_addr,
+                        &Packet::Feedback(Feedback {
+                            object_id: object.object_id,
+                            next_symbol_size: calculate_symbol_size(
+                                self.network_stats.get_network_quality(),
+                                self.config.max_symbol_size,
+                            ),
+                            digest: feedback_digest(&object.digest),
+                        }),
+                        sealed,
+                    );
+                    self.reply(index, &feedback, src_addr).await;
+                    return Ok(object);
+                }
+                // Acknowledge the size the probe had on the wire, which is
+                // what the sender is searching for.
+                Some(ReceiveEvent::Probe(probe)) => Packet::ProbeAck(ProbeAck {
+                    seq: probe.seq,
+                    size: size as u16,
+                }),
+                Some(ReceiveEvent::Hello(hello)) => {
+                    Packet::HelloAck(HelloAck { nonce: hello.nonce })
+                }
+                Some(ReceiveEvent::Retry(retry)) => Packet::Retry(retry),
+                // Handshakes are answered in the clear, the response being
+                // what the session keys come out of.
+                Some(ReceiveEvent::Handshake(init)) => {
+                    #[cfg(feature = "crypto")]
+                    if let Some(response) = self.answer_handshake(src_addr, init) {
+                        let response = Packet::HandshakeResponse(response).serialize();
+                        self.reply(index, &response, src_addr).await;
+                    }
+                    #[cfg(not(feature = "crypto"))]
+                    let _ = init;
+                    continue;
+                }
+                Some(ReceiveEvent::IntegrityFailure { object_id, .. }) => {
+                    return Err(NyxError::IntegrityFailure { object_id });
+                }
+                None => continue,
+            };
+            let reply = self.encode(src_addr, &reply, sealed);
+            if !self.reassembler.is_validated(src_addr)
+                && reply.len() > self.config.amplification_limit.saturating_mul(size)
+            {
+                self.reassembler.handle_withheld_reply();
+                continue;
+            }
+            self.reply(index, &reply, src_addr).await;
+        }
+    }
+
+    /// Sends `reply` to `peer` over the transport its request came in on,
+    /// counting rather than returning a failure.
+    async fn reply(&mut self, index: usize, reply: &[u8], peer: SocketAddr) {
+        if self.transports[index].send_to(reply, peer).await.is_err() {
+            self.reassembler.handle_failed_reply();
+        }
+    }
+
+    /// Runs the responder's side of a handshake from `peer`. The session it
+    /// establishes becomes the peer's current one once a datagram sealed
+    /// under it arrives; until then the current one stays, as the handshake
+    /// may be a replay. A retransmitted handshake gets the response already
+    /// sent for it.
+    #[cfg(feature = "crypto")]
+    fn answer_handshake(&mut self, peer: SocketAddr, init: Handshake) -> Option<Handshake> {
+        let noise = self.config.noise.as_ref()?;
+        if let Some(keys) = self.keys.get(&peer) {
+            if keys.is_retired(init.session_id) {
+                return None;
+            }
+            if let Some(response) = &keys.handshake {
+                if response.session_id == init.session_id {
+                    return Some(response.clone());
+                }
+            }
+            if keys.live.as_ref().map(SessionKeys::session_id) == Some(init.session_id) {
+                // The session this starts is already up.
+                return None;
+            }
+        }
+        match noise.respond(self.config.psk.as_ref(), &init) {
+            Ok((response, keys)) if self.config.acl.permits_identity(keys.identity()) => {
+                let keys = keys
+                    .with_rekey(self.config.rekey)
+                    .with_padding(self.config.padding);
+                if !make_room(
+                    &mut self.keys,
+                    &self.reassembler,
+                    self.config.limits.max_sessions,
+                    peer,
+                ) {
+                    self.reassembler.handle_handshake_failure();
+                    return None;
+                }
+                let peer_keys = self.keys.entry(peer).or_default();
+                peer_keys.pending = Some(keys);
+                peer_keys.handshake = Some(response.clone());
+                Some(response)
+            }
+            _ => {
+                self.reassembler.handle_handshake_failure();
+                None
+            }
+        }
+    }
+
+    /// The id of `peer`'s live session, if it has one.
+    #[cfg(feature = "crypto")]
+    fn live_session_id(&self, peer: SocketAddr) -> Option<u64> {
+        self.keys
+            .get(&peer)?
+            .live
+            .as_ref()
+            .map(SessionKeys::session_id)
+    }
+
+    fn encode(&mut self, peer: SocketAddr, packet: &Packet, sealed: bool) -> Vec<u8> {
+        let datagram = packet.serialize();
+        #[cfg(feature = "crypto")]
+        if let Some(keys) = self
+            .keys
+            .get_mut(&peer)
+            .filter(|_| sealed)
+            .and_then(|keys| keys.live.as_mut())
+        {
+            let sealed = keys.seal(&datagram);
+            let padding = sealed.len() - SEAL_OVERHEAD - datagram.len();
+            self.reassembler.handle_padding_sent(padding);
+            return sealed;
+        }
+        #[cfg(not(feature = "crypto"))]
+        let _ = (peer, sealed);
+        datagram
+    }
+}
+
+/// Handles a datagram of `peer`'s pending session keyed by a PSK alone,
+/// which could be a recording of an old session. A hello echoing a
+/// cookie bound to the session, which only the holder of its keys can
+/// read, makes it the peer's live session. Other hellos get a retry
+/// sealed under the session carrying such a cookie, and anything else
+/// is dropped.
+#[cfg(feature = "crypto")]
+fn challenge(
+    keys: &mut HashMap<SocketAddr, PeerKeys>,
+    session_cookies: &AddressValidator,
+    reassembler: &mut Reassembler,
+    peer: SocketAddr,
+    datagram: &[u8],
+) -> Challenge {
+    let hello = match Packet::parse(datagram) {
+        Ok(Packet::Hello(hello)) => Some(hello),
+        _ => None,
+    };
+    let keys = keys.get_mut(&peer).expect("opened under them");
+    let (Some(hello), Some(pending)) = (hello, keys.pending.as_mut()) else {
+        reassembler.handle_unproven(false);
+        return Challenge::Dropped;
+    };
+    let session_id = pending.session_id();
+    if session_cookies.check_session(peer, session_id, &hello.cookie) {
+        keys.promote();
+        return Challenge::Passed;
+    }
+    reassembler.handle_unproven(true);
+    let retry = Packet::Retry(Retry {
+        nonce: hello.nonce,
+        cookie: session_cookies.session_cookie(peer, session_id),
+    })
+    .serialize();
+    let sealed = pending.seal(&retry);
+    reassembler.handle_padding_sent(sealed.len() - SEAL_OVERHEAD - retry.len());
+    Challenge::Retry(sealed)
+}
+
+/// Makes room to hold keys for `peer` alongside at most `max_sessions - 1`
+/// other peers, if need be by dropping the keys of peers the reassembler
+/// holds no session for, such as handshakes never followed up. Returns
+/// false if there is no room all the same.
+#[cfg(feature = "crypto")]
+fn make_room(
+    keys: &mut HashMap<SocketAddr, PeerKeys>,
+    reassembler: &Reassembler,
+    max_sessions: usize,
+    peer: SocketAddr,
+) -> bool {
+    if keys.contains_key(&peer) || keys.len() < max_sessions {
+        return true;
+    }
+    keys.retain(|peer, _| reassembler.has_session(*peer));
+    keys.len() < max_sessions
+}
+
+/// Opens a sealed datagram with the keys cached for `peer`, and tells
+/// whether the session it belongs to has shown it is not a replay.
+/// Datagrams of sessions the peer has moved on from are replays.
+///
+/// A datagram of the peer's pending Noise session proves the handshake
+/// behind it was not a replay, and makes that session the live one. Without
+/// Noise, a datagram of a new session is opened with keys derived from the
+/// PSK, or failing that from each named one in turn. Once it has
+/// authenticated, those keys become the peer's pending session, which has
+/// yet to pass a [`Challenge`] before it replaces the live one; until then
+/// a recording of an old session cannot take over, and forged session ids
+/// cannot even get that far. A new session whose identity the access list
+/// refuses fails with [`NyxError::Denied`]. With Noise, only handshakes
+/// start sessions.
+#[cfg(feature = "crypto")]
+fn open_datagram(
+    keys: &mut HashMap<SocketAddr, PeerKeys>,
+    reassembler: &Reassembler,
+    config: &ReceiverConfig,
+    peer: SocketAddr,
+    sealed: &[u8],
+) -> Result<(Vec<u8>, bool)> {
+    let session_id = peek_session_id(sealed).ok_or(NyxError::AuthFailure)?;
+    if let Some(keys) = keys.get_mut(&peer) {
+        if let Some(live) = keys
+            .live
+            .as_mut()
+            .filter(|live| live.session_id() == session_id)
+        {
+            return Ok((live.open(sealed)?, true));
+        }
+        if let Some(pending) = keys
+            .pending
+            .as_mut()
+            .filter(|pending| pending.session_id() == session_id)
+        {
+            let datagram = pending.open(sealed)?;
+            if config.noise.is_none() {
+                return Ok((datagram, false));
+            }
+            keys.promote();
+            return Ok((datagram, true));
+        }
+        if keys.is_retired(session_id) {
+            let packet_number = peek_packet_number(sealed).unwrap_or_default();
+            return Err(NyxError::Replayed { packet_number });
+        }
+    }
+    if config.noise.is_some() {
+        return Err(NyxError::AuthFailure);
+    }
+    let named = config
+        .psk_identities
+        .iter()
+        .map(|(name, psk)| (Some(Identity::Psk(name.clone())), psk));
+    for (identity, psk) in config.psk.iter().map(|psk| (None, psk)).chain(named) {
+        let mut session = SessionKeys::new(psk, session_id, Role::Receiver)
+            .with_rekey(config.rekey)
+            .with_padding(config.padding);
+        if let Some(identity) = identity {
+            session = session.with_identity(identity);
+        }
+        let datagram = match session.open(sealed) {
+            Err(NyxError::AuthFailure) => continue,
+            result => result?,
+        };
+        if !config.acl.permits_identity(session.identity()) {
+            return Err(NyxError::Denied);
+        }
+        if !make_room(keys, reassembler, config.limits.max_sessions, peer) {
+            return Err(NyxError::ResourceLimit("sessions"));
+        }
+        keys.entry(peer).or_default().pending = Some(session);
+        return Ok((datagram, false));
+    }
+    Err(NyxError::AuthFailure)
+}
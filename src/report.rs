@@ -0,0 +1,514 @@
+// report.rs
+//
+// Benchmark results in a form that can be kept and compared: one record per
+// protocol, scenario and payload size. The benchmarks write them as CSV and
+// JSON, and `nyxpsi-bench-report` renders them into the README's results
+// table and SVG plots, so that the published numbers come from a run rather
+// than being copied by hand. CSV has a header line:
+//
+//     protocol,scenario,loss_rate,payload_size,runs,completion_time_ms,goodput_mbps,overhead,success_rate
+//     TCP,10% loss,0.1,1000000,11,1858.2,6.08,0.11,0.91
+//
+// (fields with commas or quotes in them are quoted) and JSON is an array of
+// objects with the same keys. `overhead` is empty, or null, for transfers
+// that delivered nothing.
+use std::{fmt, fs, io, path::Path, str::FromStr, time::Duration};
+
+const HEADER: &str = "protocol,scenario,loss_rate,payload_size,runs,completion_time_ms,goodput_mbps,overhead,success_rate";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchRecord {
+    /// Such as `TCP` or `nyxpsi (udp)`.
+    pub protocol: String,
+    /// What the link did, such as `10% loss`.
+    pub scenario: String,
+    /// The share of packets the scenario loses on average.
+    pub loss_rate: f64,
+    /// Bytes transferred.
+    pub payload_size: u64,
+    /// Transfers the averages are taken over.
+    pub runs: u64,
+    /// Mean time until the receiver had the payload, or gave up.
+    pub completion_time: Duration,
+    /// Payload delivered per second, in Mbit/s.
+    pub goodput: f64,
+    /// Bytes on the link per byte of payload delivered, less one. `None`
+    /// if nothing was delivered.
+    pub overhead: Option<f64>,
+    /// The share of transfers that completed.
+    pub success_rate: f64,
+}
+
+/// Records in the order they were measured, which is the order tables and
+/// plots list protocols and scenarios in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BenchResults {
+    pub records: Vec<BenchRecord>,
+}
+
+/// What a plot shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    CompletionTime,
+    Goodput,
+    SuccessRate,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 3] = [Metric::CompletionTime, Metric::Goodput, Metric::SuccessRate];
+
+    /// A name for files of this plot.
+    pub fn file_stem(self) -> &'static str {
+        match self {
+            Metric::CompletionTime => "completion-time",
+            Metric::Goodput => "goodput",
+            Metric::SuccessRate => "success-rate",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Metric::CompletionTime => "Completion time (s)",
+            Metric::Goodput => "Goodput (Mbit/s)",
+            Metric::SuccessRate => "Success rate (%)",
+        }
+    }
+
+    fn value(self, record: &BenchRecord) -> f64 {
+        match self {
+            Metric::CompletionTime => record.completion_time.as_secs_f64(),
+            Metric::Goodput => record.goodput,
+            Metric::SuccessRate => record.success_rate * 100.0,
+        }
+    }
+}
+
+/// A payload size as the benchmarks label it, such as `64 kB` or `1 MB`.
+pub fn size_label(bytes: u64) -> String {
+    if bytes >= 1_000_000 {
+        format!("{} MB", bytes / 1_000_000)
+    } else if bytes >= 1_000 {
+        format!("{} kB", bytes / 1_000)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+impl BenchResults {
+    /// Reads results from a CSV or JSON file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(&path)?.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.as_ref().display(), e),
+            )
+        })
+    }
+
+    /// Writes the results as JSON if `path` ends in `.json`, and as CSV
+    /// otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            fs::write(path, self.to_json())
+        } else {
+            fs::write(path, self.to_string())
+        }
+    }
+
+    pub fn from_csv(s: &str) -> Result<Self, String> {
+        let mut records = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            if line.trim().is_empty() || (number == 0 && line.starts_with("protocol")) {
+                continue;
+            }
+            let record = split_csv(line).and_then(|fields| match &fields[..] {
+                [protocol, scenario, numbers @ ..] if numbers.len() == 7 => {
+                    parse_record(protocol, scenario, numbers)
+                }
+                _ => Err(format!("expected 9 fields: {}", line)),
+            });
+            records.push(record.map_err(|e| format!("line {}: {}", number + 1, e))?);
+        }
+        Ok(BenchResults { records })
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(s).map_err(|e| format!("not JSON: {}", e))?;
+        let entries = value.as_array().ok_or("expected an array of records")?;
+        let mut records = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let text = |name| entry.get(name).and_then(|v: &serde_json::Value| v.as_str());
+            let number = |name| match entry.get(name) {
+                Some(serde_json::Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            };
+            let record = match (text("protocol"), text("scenario")) {
+                (Some(protocol), Some(scenario)) => parse_record(
+                    protocol,
+                    scenario,
+                    &[
+                        "loss_rate",
+                        "payload_size",
+                        "runs",
+                        "completion_time_ms",
+                        "goodput_mbps",
+                        "overhead",
+                        "success_rate",
+                    ]
+                    .map(number),
+                ),
+                _ => Err("expected protocol and scenario".to_string()),
+            };
+            records.push(record.map_err(|e| format!("record {}: {}", index, e))?);
+        }
+        Ok(BenchResults { records })
+    }
+
+    pub fn to_json(&self) -> String {
+        let records = self
+            .records
+            .iter()
+            .map(|record| {
+                serde_json::json!({
+                    "protocol": record.protocol,
+                    "scenario": record.scenario,
+                    "loss_rate": record.loss_rate,
+                    "payload_size": record.payload_size,
+                    "runs": record.runs,
+                    "completion_time_ms": millis(record.completion_time),
+                    "goodput_mbps": record.goodput,
+                    "overhead": record.overhead,
+                    "success_rate": record.success_rate,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&records).expect("records serialize")
+    }
+
+    /// The payload sizes measured, in order.
+    pub fn payload_sizes(&self) -> Vec<u64> {
+        let mut sizes = Vec::new();
+        for record in &self.records {
+            if !sizes.contains(&record.payload_size) {
+                sizes.push(record.payload_size);
+            }
+        }
+        sizes
+    }
+
+    /// A markdown table of the transfers of `payload_size`: a row per
+    /// protocol and a column per scenario, each cell the completion time
+    /// with the success rate in parentheses.
+    pub fn markdown_table(&self, payload_size: u64) -> String {
+        let (protocols, scenarios, records) = self.grid(payload_size);
+        let mut rows = vec![std::iter::once("Protocol".to_string())
+            .chain(scenarios.iter().map(|scenario| capitalize(scenario)))
+            .collect::<Vec<_>>()];
+        for protocol in &protocols {
+            let mut row = vec![protocol.to_string()];
+            for scenario in &scenarios {
+                row.push(
+                    match records
+                        .iter()
+                        .find(|r| &r.protocol == protocol && &r.scenario == scenario)
+                    {
+                        Some(record) => format!(
+                            "{} ({:.0}%)",
+                            seconds(record.completion_time),
+                            record.success_rate * 100.0
+                        ),
+                        None => "–".to_string(),
+                    },
+                );
+            }
+            rows.push(row);
+        }
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| {
+                    format!("{}{}", cell, " ".repeat(width - cell.chars().count()))
+                })
+                .collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut table = line(rows[0].clone());
+        table += &line(widths.iter().map(|&width| "-".repeat(width)).collect());
+        for row in rows.into_iter().skip(1) {
+            table += &line(row);
+        }
+        table
+    }
+
+    /// An SVG bar chart of `metric` for the transfers of `payload_size`:
+    /// a group of bars per scenario, a bar per protocol.
+    pub fn svg_plot(&self, payload_size: u64, metric: Metric) -> String {
+        const WIDTH: f64 = 720.0;
+        const HEIGHT: f64 = 400.0;
+        const LEFT: f64 = 60.0;
+        const RIGHT: f64 = 170.0;
+        const TOP: f64 = 40.0;
+        const BOTTOM: f64 = 60.0;
+        const COLOURS: [&str; 6] = [
+            "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#b07aa1",
+        ];
+        let (protocols, scenarios, records) = self.grid(payload_size);
+        let top = nice_ceiling(records.iter().map(|r| metric.value(r)).fold(0.0, f64::max));
+        let plot_width = WIDTH - LEFT - RIGHT;
+        let plot_height = HEIGHT - TOP - BOTTOM;
+        let y = |value: f64| TOP + plot_height * (1.0 - value / top);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+             font-family=\"sans-serif\" font-size=\"11\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+             <text x=\"{}\" y=\"20\" font-size=\"14\" text-anchor=\"middle\">{} for {}</text>\n",
+            LEFT + plot_width / 2.0,
+            metric.label(),
+            size_label(payload_size)
+        );
+        for step in 0..=4 {
+            let value = top * step as f64 / 4.0;
+            svg += &format!(
+                "<line x1=\"{LEFT}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\n\
+                 <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+                LEFT + plot_width,
+                LEFT - 6.0,
+                y(value) + 4.0,
+                trim_number(value),
+                y = y(value),
+            );
+        }
+        let group_width = plot_width / scenarios.len().max(1) as f64;
+        let bar_width = group_width * 0.8 / protocols.len().max(1) as f64;
+        for (group, scenario) in scenarios.iter().enumerate() {
+            let group_left = LEFT + group_width * group as f64;
+            for (index, protocol) in protocols.iter().enumerate() {
+                let Some(record) = records
+                    .iter()
+                    .find(|r| &r.protocol == protocol && &r.scenario == scenario)
+                else {
+                    continue;
+                };
+                let value = metric.value(record);
+                svg += &format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\">\
+                     <title>{}, {}: {}</title></rect>\n",
+                    group_left + group_width * 0.1 + bar_width * index as f64,
+                    y(value),
+                    bar_width,
+                    TOP + plot_height - y(value),
+                    COLOURS[index % COLOURS.len()],
+                    escape(protocol),
+                    escape(scenario),
+                    trim_number(value)
+                );
+            }
+            svg += &format!(
+                "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                group_left + group_width / 2.0,
+                TOP + plot_height + 16.0,
+                escape(scenario)
+            );
+        }
+        svg += &format!(
+            "<line x1=\"{LEFT}\" x2=\"{LEFT}\" y1=\"{TOP}\" y2=\"{0}\" stroke=\"black\"/>\n\
+             <line x1=\"{LEFT}\" x2=\"{1}\" y1=\"{0}\" y2=\"{0}\" stroke=\"black\"/>\n",
+            TOP + plot_height,
+            LEFT + plot_width
+        );
+        for (index, protocol) in protocols.iter().enumerate() {
+            let top = TOP + 18.0 * index as f64;
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{top}\" width=\"12\" height=\"12\" fill=\"{}\"/>\n\
+                 <text x=\"{}\" y=\"{}\">{}</text>\n",
+                WIDTH - RIGHT + 16.0,
+                COLOURS[index % COLOURS.len()],
+                WIDTH - RIGHT + 34.0,
+                top + 10.0,
+                escape(protocol)
+            );
+        }
+        svg += "</svg>\n";
+        svg
+    }
+
+    /// The protocols, scenarios and records of `payload_size`, each in the
+    /// order first measured.
+    fn grid(&self, payload_size: u64) -> (Vec<&str>, Vec<&str>, Vec<&BenchRecord>) {
+        let records: Vec<&BenchRecord> = self
+            .records
+            .iter()
+            .filter(|r| r.payload_size == payload_size)
+            .collect();
+        let (mut protocols, mut scenarios) = (Vec::new(), Vec::new());
+        for record in &records {
+            if !protocols.contains(&record.protocol.as_str()) {
+                protocols.push(record.protocol.as_str());
+            }
+            if !scenarios.contains(&record.scenario.as_str()) {
+                scenarios.push(record.scenario.as_str());
+            }
+        }
+        (protocols, scenarios, records)
+    }
+}
+
+impl FromStr for BenchResults {
+    type Err = String;
+
+    /// Parses results as JSON if they look like JSON, and as CSV otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('[') {
+            BenchResults::from_json(s)
+        } else {
+            BenchResults::from_csv(s)
+        }
+    }
+}
+
+impl fmt::Display for BenchResults {
+    /// Writes the results as CSV.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for record in &self.records {
+            writeln!(
+                f,
+                "{},{},{},{},{},{},{},{},{}",
+                quote(&record.protocol),
+                quote(&record.scenario),
+                record.loss_rate,
+                record.payload_size,
+                record.runs,
+                millis(record.completion_time),
+                record.goodput,
+                record.overhead.map_or(String::new(), |o| o.to_string()),
+                record.success_rate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_record(protocol: &str, scenario: &str, numbers: &[String]) -> Result<BenchRecord, String> {
+    let [loss_rate, payload_size, runs, completion_time, goodput, overhead, success_rate] = numbers
+    else {
+        return Err(format!("expected 7 numbers, not {}", numbers.len()));
+    };
+    let float = |s: &String| {
+        s.parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite())
+            .ok_or_else(|| format!("not a number: {}", s))
+    };
+    let integer = |s: &String| s.parse::<u64>().map_err(|_| format!("not a count: {}", s));
+    let completion_time = float(completion_time)?;
+    if completion_time < 0.0 {
+        return Err(format!("negative time: {}", completion_time));
+    }
+    Ok(BenchRecord {
+        protocol: protocol.to_string(),
+        scenario: scenario.to_string(),
+        loss_rate: float(loss_rate)?,
+        payload_size: integer(payload_size)?,
+        runs: integer(runs)?,
+        completion_time: Duration::from_nanos((completion_time * 1e6).round() as u64),
+        goodput: float(goodput)?,
+        overhead: match overhead.as_str() {
+            "" => None,
+            _ => Some(float(overhead)?),
+        },
+        success_rate: float(success_rate)?,
+    })
+}
+
+/// The fields of a CSV line, unquoted.
+fn split_csv(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quote: {}", line));
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1e6
+}
+
+/// A time in seconds, with enough places to tell short ones apart.
+fn seconds(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs > 0.0 && secs < 0.01 {
+        format!("{:.3}s", secs)
+    } else {
+        format!("{:.2}s", secs)
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The smallest of 1, 2 and 5 times a power of ten at or above `value`, so
+/// that an axis up to it has round steps.
+fn nice_ceiling(value: f64) -> f64 {
+    if value <= 0.0 || !value.is_finite() {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|&ceiling| ceiling >= value)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn trim_number(value: f64) -> String {
+    let s = format!("{:.3}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,603 @@
+// sender.rs
+#[cfg(feature = "crypto")]
+use crate::{
+    crypto::{
+        random_session_id, NoiseInitiator, Padding, Psk, RekeyPolicy, Role, SessionKeys,
+        PADDED_CONTROL_SIZE, PADDING_TRAILER_SIZE, SEAL_OVERHEAD,
+    },
+    packet::{is_handshake, HANDSHAKE_OVERHEAD, MAX_HANDSHAKE_MESSAGE},
+};
+use crate::{
+    error::{NyxError, Result},
+    packet::{
+        feedback_digest, object_digest, DataPacket, Hello, Packet, Probe, COOKIE_SIZE,
+        DATA_OVERHEAD, FEEDBACK_SIZE, HELLO_SIZE, PROBE_ACK_SIZE,
+    },
+    pmtu::{is_too_big, PmtuSearch},
+    stats::{
+        calculate_symbol_size, NetworkStats, MAX_JUMBO_SYMBOL_SIZE, MAX_SYMBOL_SIZE,
+        MIN_SYMBOL_SIZE,
+    },
+    transport::{self, DatagramTransport, TransportKind},
+};
+use rand::random;
+use raptorq::{Encoder, ObjectTransmissionInformation};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+pub const MIN_PACKETS: u32 = 5;
+pub const MAX_PACKETS: u32 = 20;
+const TIMEOUT_MS: u64 = 1000;
+const PROBE_TIMEOUT_MS: u64 = 250;
+const HANDSHAKE_TIMEOUT_MS: u64 = 300;
+/// Hellos, or handshake messages, sent over one transport before giving up
+/// on it.
+const HELLO_ATTEMPTS: u32 = 3;
+/// Unanswered objects in a row after which the path MTU is presumed to have
+/// shrunk under us.
+const BLACK_HOLE_THRESHOLD: u32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct SenderConfig {
+    /// Transports [`Sender::connect_with_config`] tries, most preferred
+    /// first. The first one the receiver answers a hello on is used.
+    pub transports: Vec<TransportKind>,
+    /// Upper bound on the symbol size, whatever the receiver asks for. Up to
+    /// [`MAX_JUMBO_SYMBOL_SIZE`] on paths that carry jumbo frames.
+    pub max_symbol_size: u16,
+    /// How long to wait for feedback after the last packet of an object.
+    pub timeout: Duration,
+    /// How long to wait for each path MTU probe to be acknowledged.
+    pub probe_timeout: Duration,
+    /// How long to wait for each hello or handshake message to be answered.
+    pub handshake_timeout: Duration,
+    /// Seal every datagram with keys derived from this pre-shared key. The
+    /// receiver must be configured with the same key.
+    #[cfg(feature = "crypto")]
+    pub psk: Option<Psk>,
+    /// Once hellos are answered, establish session keys in a Noise
+    /// handshake with this identity, authenticating the receiver by its
+    /// static key.
+    /// If `psk` is set as well, the handshake also requires it.
+    #[cfg(feature = "crypto")]
+    pub noise: Option<NoiseInitiator>,
+    /// When session keys are rotated.
+    #[cfg(feature = "crypto")]
+    pub rekey: RekeyPolicy,
+    /// Pad sealed datagrams to hide how large their contents are.
+    #[cfg(feature = "crypto")]
+    pub padding: Option<Padding>,
+}
+
+impl Default for SenderConfig {
+    fn default() -> Self {
+        SenderConfig {
+            transports: vec![TransportKind::UdpLite, TransportKind::Udp],
+            max_symbol_size: MAX_SYMBOL_SIZE,
+            timeout: Duration::from_millis(TIMEOUT_MS),
+            probe_timeout: Duration::from_millis(PROBE_TIMEOUT_MS),
+            handshake_timeout: Duration::from_millis(HANDSHAKE_TIMEOUT_MS),
+            #[cfg(feature = "crypto")]
+            psk: None,
+            #[cfg(feature = "crypto")]
+            noise: None,
+            #[cfg(feature = "crypto")]
+            rekey: RekeyPolicy::default(),
+            #[cfg(feature = "crypto")]
+            padding: None,
+        }
+    }
+}
+
+/// Feedback for one successfully delivered object.
+#[derive(Clone, Copy, Debug)]
+pub struct Delivery {
+    pub object_id: u32,
+    pub packets_sent: u32,
+    pub rtt: Duration,
+    pub next_symbol_size: u16,
+    /// Bytes of padding the object's packets carried.
+    pub padding_bytes: u64,
+}
+
+/// Sends objects to a single receiver, adapting the number of packets and
+/// the symbol size to the feedback it gets back.
+pub struct Sender {
+    transport: Box<dyn DatagramTransport>,
+    peer: SocketAddr,
+    network_stats: NetworkStats,
+    packets_to_send: u32,
+    consecutive_successes: u32,
+    consecutive_failures: u32,
+    consecutive_timeouts: u32,
+    symbol_size: u16,
+    next_object_id: u32,
+    pmtu: PmtuSearch,
+    next_probe_seq: u32,
+    padding_bytes: u64,
+    #[cfg(feature = "crypto")]
+    keys: Option<SessionKeys>,
+    /// Whether the receiver has acknowledged a hello of the session keyed
+    /// by the PSK, which it needs before taking the session for live.
+    #[cfg(feature = "crypto")]
+    hello_acked: bool,
+    config: SenderConfig,
+}
+
+impl Sender {
+    pub async fn connect(peer: SocketAddr) -> Result<Self> {
+        Self::connect_with_config(peer, SenderConfig::default()).await
+    }
+
+    /// Tries each of `config.transports` in order and keeps the first one
+    /// over which the receiver answers a [`handshake`](Self::handshake).
+    ///
+    /// If none works, fails with the error from the last one tried, which
+    /// is [`NyxError::Timeout`] if the receiver simply did not answer.
+    pub async fn connect_with_config(peer: SocketAddr, config: SenderConfig) -> Result<Self> {
+        let local_addr = if peer.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        let mut last_error = None;
+        for &kind in &config.transports {
+            let transport = match transport::bind(kind, local_addr) {
+                Ok(transport) => transport,
+                Err(e) => {
+                    last_error = Some(e.into());
+                    continue;
+                }
+            };
+            let mut sender = Self::with_transport(transport, peer, config.clone())?;
+            match sender.handshake().await {
+                Ok(()) => return Ok(sender),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or(NyxError::Timeout))
+    }
+
+    /// Sends over an already bound transport, without a handshake. A Noise
+    /// handshake, if configured, runs before the first probe or object
+    /// instead. `config.transports` is ignored.
+    pub fn with_transport(
+        transport: Box<dyn DatagramTransport>,
+        peer: SocketAddr,
+        mut config: SenderConfig,
+    ) -> Result<Self> {
+        config.max_symbol_size = config
+            .max_symbol_size
+            .clamp(MIN_SYMBOL_SIZE, MAX_JUMBO_SYMBOL_SIZE);
+        transport.set_dont_fragment()?;
+        #[cfg(feature = "crypto")]
+        let keys = config
+            .psk
+            .as_ref()
+            .filter(|_| config.noise.is_none())
+            .map(|psk| {
+                SessionKeys::new(psk, random_session_id(), Role::Sender)
+                    .with_rekey(config.rekey)
+                    .with_padding(config.padding)
+            });
+        let mut sender = Sender {
+            transport,
+            peer,
+            network_stats: NetworkStats::new(),
+            packets_to_send: MIN_PACKETS,
+            consecutive_successes: 0,
+            consecutive_failures: 0,
+            consecutive_timeouts: 0,
+            symbol_size: MIN_SYMBOL_SIZE,
+            next_object_id: 0,
+            pmtu: PmtuSearch::new(0),
+            next_probe_seq: 0,
+            padding_bytes: 0,
+            #[cfg(feature = "crypto")]
+            keys,
+            #[cfg(feature = "crypto")]
+            hello_acked: false,
+            config,
+        };
+        let max_datagram_size = sender.config.max_symbol_size + sender.data_overhead();
+        sender.pmtu = PmtuSearch::new(max_datagram_size);
+        Ok(sender)
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn transport_kind(&self) -> TransportKind {
+        self.transport.kind()
+    }
+
+    pub fn packets_to_send(&self) -> u32 {
+        self.packets_to_send
+    }
+
+    pub fn symbol_size(&self) -> u16 {
+        self.symbol_size
+    }
+
+    /// The largest datagram known to reach the receiver unfragmented.
+    pub fn path_mtu(&self) -> u16 {
+        self.pmtu.plpmtu()
+    }
+
+    /// The largest symbol size that fits both the configuration and the
+    /// path MTU.
+    pub fn max_symbol_size(&self) -> u16 {
+        let path_limit = self.pmtu.plpmtu().saturating_sub(self.data_overhead());
+        self.config
+            .max_symbol_size
+            .min(path_limit)
+            .max(MIN_SYMBOL_SIZE)
+    }
+
+    pub fn network_quality(&self) -> f64 {
+        self.network_stats.get_network_quality()
+    }
+
+    /// The symbol size our own measurements would pick, for comparison with
+    /// what the receiver asks for.
+    pub fn calculated_symbol_size(&self) -> u16 {
+        calculate_symbol_size(self.network_quality(), self.max_symbol_size())
+    }
+
+    /// Checks that the receiver answers over this transport by sending it
+    /// hellos until one is acknowledged, echoing the cookie of any retry the
+    /// receiver answers with to validate our address or, under a PSK alone,
+    /// to show the session is not a recording of an old one. With Noise
+    /// configured, then runs a key exchange, starting a new session.
+    ///
+    /// Returns [`NyxError::Timeout`] if the receiver never answers, and
+    /// [`NyxError::AuthFailure`] if a handshake response does not prove it
+    /// holds the expected static key.
+    pub async fn handshake(&mut self) -> Result<()> {
+        #[cfg(feature = "crypto")]
+        if self.config.noise.is_some() {
+            // Hellos of a new Noise session go in the clear.
+            self.keys = None;
+        }
+        let nonce = random();
+        let mut cookie = [0; COOKIE_SIZE];
+        let mut attempts = 0;
+        while attempts < HELLO_ATTEMPTS {
+            let hello = self.encode(&Packet::Hello(Hello { nonce, cookie }));
+            self.transport.send_to(&hello, self.peer).await?;
+            let deadline = Instant::now() + self.config.handshake_timeout;
+            let answer = self
+                .await_packet(HELLO_SIZE, deadline, |packet| match packet {
+                    Packet::HelloAck(ack) if ack.nonce == nonce => Some(None),
+                    Packet::Retry(retry) if retry.nonce == nonce => Some(Some(retry.cookie)),
+                    _ => None,
+                })
+                .await;
+            match answer {
+                Ok(None) => {
+                    #[cfg(feature = "crypto")]
+                    {
+                        self.hello_acked = true;
+                    }
+                    #[cfg(feature = "crypto")]
+                    if self.config.noise.is_some() {
+                        return self.key_exchange().await;
+                    }
+                    return Ok(());
+                }
+                // A retry is an answer, not a loss; only one refusing the
+                // cookie we just echoed counts against the attempts.
+                Ok(Some(retry_cookie)) => {
+                    if retry_cookie == cookie {
+                        attempts += 1;
+                    }
+                    cookie = retry_cookie;
+                }
+                Err(NyxError::Timeout) => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(NyxError::Timeout)
+    }
+
+    /// Sends the first handshake message, identical each time, until the
+    /// receiver responds, and keys the session from the response.
+    #[cfg(feature = "crypto")]
+    async fn key_exchange(&mut self) -> Result<()> {
+        let noise = self.config.noise.as_ref().expect("checked by caller");
+        let (init, initiation) = noise.initiate(self.config.psk.as_ref())?;
+        let init = Packet::HandshakeInit(init).serialize();
+        let session_id = initiation.session_id();
+        for _ in 0..HELLO_ATTEMPTS {
+            self.transport.send_to(&init, self.peer).await?;
+            let deadline = Instant::now() + self.config.handshake_timeout;
+            let response = self
+                .await_packet(
+                    HANDSHAKE_OVERHEAD + MAX_HANDSHAKE_MESSAGE,
+                    deadline,
+                    |packet| match packet {
+                        Packet::HandshakeResponse(response)
+                            if response.session_id == session_id =>
+                        {
+                            Some(response)
+                        }
+                        _ => None,
+                    },
+                )
+                .await;
+            match response {
+                Ok(response) => {
+                    let keys = initiation.finish(&response)?;
+                    self.keys = Some(
+                        keys.with_rekey(self.config.rekey)
+                            .with_padding(self.config.padding),
+                    );
+                    return Ok(());
+                }
+                Err(NyxError::Timeout) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(NyxError::Timeout)
+    }
+
+    /// Probes the path for the largest datagram size that gets through with
+    /// the Don't Fragment bit set, and returns it.
+    ///
+    /// Runs automatically before the first object and whenever the previous
+    /// result has gone stale.
+    pub async fn discover_path_mtu(&mut self) -> Result<u16> {
+        if self.needs_handshake() {
+            self.handshake().await?;
+        }
+        while let Some(size) = self.pmtu.next_probe() {
+            let seq = self.next_probe_seq;
+            self.next_probe_seq = self.next_probe_seq.wrapping_add(1);
+            // `size` is what goes on the wire, sealing included.
+            let probe = self.encode(&Packet::Probe(Probe {
+                seq,
+                size: size - self.seal_overhead() as u16,
+            }));
+            match self.transport.send_to(&probe, self.peer).await {
+                Ok(_) => {}
+                Err(e) if is_too_big(&e) => {
+                    self.pmtu.on_too_big(size);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            let deadline = Instant::now() + self.config.probe_timeout;
+            let acked = self
+                .await_packet(PROBE_ACK_SIZE, deadline, |packet| match packet {
+                    Packet::ProbeAck(ack) if ack.seq == seq => Some(ack.size),
+                    _ => None,
+                })
+                .await;
+            match acked {
+                Ok(acked) => self.pmtu.on_ack(acked),
+                Err(NyxError::Timeout) => self.pmtu.on_timeout(),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.pmtu.plpmtu())
+    }
+
+    /// Encodes `data`, sends the current number of packets and waits for the
+    /// receiver to confirm it decoded the object.
+    ///
+    /// Returns [`NyxError::Timeout`](crate::NyxError::Timeout) if no feedback arrives in time; the
+    /// packet count is raised for the next attempt either way. An empty
+    /// object is [`NyxError::InvalidInput`], as RaptorQ cannot encode it.
+    pub async fn send_object(&mut self, data: &[u8]) -> Result<Delivery> {
+        if data.is_empty() {
+            return Err(NyxError::InvalidInput("empty object"));
+        }
+        if self.needs_handshake() {
+            self.handshake().await?;
+        }
+        if self.pmtu.needs_search() {
+            self.discover_path_mtu().await?;
+        }
+        let object_id = self.next_object_id;
+        self.next_object_id = self.next_object_id.wrapping_add(1);
+
+        let symbol_size = self.symbol_size.min(self.max_symbol_size());
+        let oti = ObjectTransmissionInformation::with_defaults(data.len() as u64, symbol_size);
+        let digest = object_digest(data);
+        let encoder = Encoder::new(data, oti);
+        // Each source block is decoded on its own, so each gets its packets
+        // separately: small blocks go out as `packets_to_send` packets in
+        // total, larger ones as all their source symbols plus whatever the
+        // adaptation has added on top of the minimum.
+        let packets_to_send = self.packets_to_send;
+        let packets: Vec<_> = encoder
+            .get_block_encoders()
+            .iter()
+            .flat_map(|block| {
+                let source = block.source_packets();
+                let source_symbols = source.len() as u32;
+                let total = packets_to_send.max(source_symbols + packets_to_send - MIN_PACKETS);
+                source
+                    .into_iter()
+                    .chain(block.repair_packets(0, total - source_symbols))
+            })
+            .collect();
+
+        let start_time = Instant::now();
+        let padding_before = self.padding_bytes;
+        let mut packets_sent = 0;
+        for packet in packets {
+            let datagram = self.encode(&Packet::Data(DataPacket {
+                object_id,
+                oti,
+                digest,
+                packet,
+            }));
+            match self.transport.send_to(&datagram, self.peer).await {
+                Ok(_) => packets_sent += 1,
+                // The local MTU shrank below what we probed; search again
+                // before the next object.
+                Err(e) if is_too_big(&e) => self.pmtu.on_black_hole(),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let deadline = Instant::now() + self.config.timeout;
+        let max_symbol_size = self.max_symbol_size();
+        // Feedback must name this object and echo its digest; anything else
+        // is stale, or forged by someone who never saw the symbols.
+        let feedback_digest = feedback_digest(&digest);
+        let result = self
+            .await_packet(FEEDBACK_SIZE, deadline, |packet| match packet {
+                Packet::Feedback(feedback)
+                    if feedback.object_id == object_id && feedback.digest == feedback_digest =>
+                {
+                    Some(
+                        feedback
+                            .next_symbol_size
+                            .clamp(MIN_SYMBOL_SIZE, max_symbol_size),
+                    )
+                }
+                _ => None,
+            })
+            .await;
+        match result {
+            Ok(next_symbol_size) => {
+                let rtt = start_time.elapsed();
+                self.network_stats.update(true, Some(rtt.as_millis()));
+                self.symbol_size = next_symbol_size;
+                self.on_success();
+                Ok(Delivery {
+                    object_id,
+                    packets_sent,
+                    rtt,
+                    next_symbol_size,
+                    padding_bytes: self.padding_bytes - padding_before,
+                })
+            }
+            Err(e) => {
+                self.network_stats.update(false, None);
+                self.on_failure();
+                Err(e)
+            }
+        }
+    }
+
+    /// Waits until `deadline` for a datagram from the peer that `accept`
+    /// maps to a value. Anything else (late feedback for an earlier object,
+    /// garbage, forgeries, datagrams longer than `len` once unsealed) is
+    /// skipped rather than cutting the wait short.
+    async fn await_packet<T>(
+        &mut self,
+        len: usize,
+        deadline: Instant,
+        mut accept: impl FnMut(Packet) -> Option<T>,
+    ) -> Result<T> {
+        #[allow(unused_mut)]
+        let mut len = len;
+        #[cfg(feature = "crypto")]
+        if self.config.psk.is_some() || self.config.noise.is_some() {
+            // Room for replies padded to a constant size.
+            len = len.max(PADDED_CONTROL_SIZE);
+        }
+        let mut buf = vec![0u8; len + self.seal_overhead()];
+        loop {
+            let (size, src_addr) =
+                tokio::time::timeout_at(deadline, self.transport.recv_from(&mut buf)).await??;
+            if src_addr != self.peer || size > buf.len() {
+                continue;
+            }
+            if let Some(value) = self.decode(&buf[..size]).and_then(&mut accept) {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn encode(&mut self, packet: &Packet) -> Vec<u8> {
+        let datagram = packet.serialize();
+        #[cfg(feature = "crypto")]
+        {
+            let largest = DATA_OVERHEAD + self.max_symbol_size() as usize + PADDING_TRAILER_SIZE;
+            if let Some(keys) = &mut self.keys {
+                keys.set_padding_limit(largest);
+                let sealed = keys.seal(&datagram);
+                if self.config.padding.is_some() {
+                    self.padding_bytes += (sealed.len() - SEAL_OVERHEAD - datagram.len()) as u64;
+                }
+                return sealed;
+            }
+        }
+        datagram
+    }
+
+    /// Parses a datagram from the receiver, unsealing it first if the
+    /// session is sealed. Handshakes are always in the clear.
+    fn decode(&mut self, datagram: &[u8]) -> Option<Packet> {
+        #[cfg(feature = "crypto")]
+        if let Some(keys) = self.keys.as_mut().filter(|_| !is_handshake(datagram)) {
+            return Packet::parse(&keys.open(datagram).ok()?).ok();
+        }
+        Packet::parse(datagram).ok()
+    }
+
+    /// Whether a Noise session has yet to be established, or the receiver
+    /// has yet to acknowledge a hello of the session keyed by the PSK.
+    fn needs_handshake(&self) -> bool {
+        #[cfg(feature = "crypto")]
+        if self.config.noise.is_some() {
+            return self.keys.is_none();
+        }
+        #[cfg(feature = "crypto")]
+        if self.config.psk.is_some() {
+            return !self.hello_acked;
+        }
+        false
+    }
+
+    /// Bytes sealing adds to a datagram, not counting padding beyond the
+    /// trailer every padded datagram gets.
+    fn seal_overhead(&self) -> usize {
+        #[cfg(feature = "crypto")]
+        if self.config.psk.is_some() || self.config.noise.is_some() {
+            return match self.config.padding {
+                Some(_) => SEAL_OVERHEAD + PADDING_TRAILER_SIZE,
+                None => SEAL_OVERHEAD,
+            };
+        }
+        0
+    }
+
+    /// Bytes a data datagram carries on top of its symbol.
+    fn data_overhead(&self) -> u16 {
+        (DATA_OVERHEAD + self.seal_overhead()) as u16
+    }
+
+    fn on_success(&mut self) {
+        self.consecutive_timeouts = 0;
+        self.consecutive_successes += 1;
+        self.consecutive_failures = 0;
+        if self.consecutive_successes >= 2 && self.packets_to_send > MIN_PACKETS {
+            self.packets_to_send -= 1;
+            self.consecutive_successes = 0;
+        }
+    }
+
+    fn on_failure(&mut self) {
+        self.consecutive_timeouts += 1;
+        if self.consecutive_timeouts >= BLACK_HOLE_THRESHOLD {
+            self.pmtu.on_black_hole();
+            self.consecutive_timeouts = 0;
+        }
+        self.consecutive_failures += 1;
+        self.consecutive_successes = 0;
+        if self.consecutive_failures >= 1 && self.packets_to_send < MAX_PACKETS {
+            self.packets_to_send += 2;
+            self.consecutive_failures = 0;
+        }
+    }
+}
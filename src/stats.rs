@@ -0,0 +1,55 @@
+// stats.rs
+pub const MIN_SYMBOL_SIZE: u16 = 500;
+/// Default ceiling for the adaptive symbol size.
+pub const MAX_SYMBOL_SIZE: u16 = 2000;
+/// Largest symbol size that can be configured, for jumbo-frame paths.
+pub const MAX_JUMBO_SYMBOL_SIZE: u16 = 9000;
+const NETWORK_QUALITY_WINDOW: usize = 10;
+
+pub struct NetworkStats {
+    packet_loss_rate: f64,
+    latencies: Vec<u32>,
+}
+
+impl NetworkStats {
+    pub fn new() -> Self {
+        NetworkStats {
+            packet_loss_rate: 0.0,
+            latencies: Vec::with_capacity(NETWORK_QUALITY_WINDOW),
+        }
+    }
+
+    pub fn update(&mut self, packet_received: bool, latency: Option<u128>) {
+        self.packet_loss_rate = 0.9 * self.packet_loss_rate + 0.1 * (!packet_received as u8 as f64);
+        if let Some(lat) = latency {
+            if self.latencies.len() >= NETWORK_QUALITY_WINDOW {
+                self.latencies.remove(0);
+            }
+            self.latencies.push(lat as u32);
+        }
+    }
+
+    pub fn get_network_quality(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.5; // Default to middle quality if no data
+        }
+        let avg_latency = self.latencies.iter().sum::<u32>() as f64 / self.latencies.len() as f64;
+        let normalized_latency = 1.0 / (1.0 + avg_latency / 1000.0);
+        let packet_success_rate = 1.0 - self.packet_loss_rate;
+        (normalized_latency + packet_success_rate) / 2.0
+    }
+}
+
+impl Default for NetworkStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn calculate_symbol_size(network_quality: f64, max_symbol_size: u16) -> u16 {
+    let max_symbol_size = max_symbol_size.max(MIN_SYMBOL_SIZE);
+    let size = (MIN_SYMBOL_SIZE as f64
+        + (max_symbol_size - MIN_SYMBOL_SIZE) as f64 * network_quality) as u16;
+    let rounded_size = (size + 1) & !1; // Round to the nearest even number
+    rounded_size.clamp(MIN_SYMBOL_SIZE, max_symbol_size)
+}
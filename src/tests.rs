@@ -1,8 +1,29 @@
 #[cfg(test)]
 mod tests {
+    use crate::acl::{Acl, Identity};
+    use crate::limits::{decoder_cost, Limits};
+    use crate::packet::{
+        feedback_digest, is_handshake, object_digest, DataPacket, Feedback, Handshake, Hello,
+        Packet, COOKIE_SIZE, DATA_OVERHEAD, FEEDBACK_DIGEST_SIZE, HANDSHAKE_OVERHEAD,
+        PROTOCOL_VERSION,
+    };
+    use crate::pmtu::{PmtuSearch, PmtuState, BASE_PLPMTU};
+    use crate::report::{size_label, BenchRecord, BenchResults, Metric};
+    use crate::stats::{MAX_JUMBO_SYMBOL_SIZE, MAX_SYMBOL_SIZE, MIN_SYMBOL_SIZE};
+    use crate::transport::{
+        DatagramTransport, LinkConfig, LinkStats, LossModel, LossProcess, MemoryNetwork, Replay,
+        SimulatedTransport, Trace, TransportKind, UdpLiteTransport,
+    };
+    use crate::{
+        NyxError, Reassembler, ReceiveEvent, Receiver, ReceiverConfig, ReceiverStats, Sender,
+        SenderConfig,
+    };
+    use rand::rngs::StdRng;
     use rand::thread_rng;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
     use raptorq::{Decoder, Encoder, EncodingPacket, ObjectTransmissionInformation};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
 
     const DATA_SIZE: u64 = 1300;
     const SYMBOL_SIZE: u16 = 1000;
@@ -90,4 +111,2115 @@ mod tests {
 
         assert!(decoded, "Failed to decode with packet loss");
     }
+
+    #[test]
+    fn test_packet_parse_errors() {
+        assert!(matches!(
+            Packet::parse(&[PROTOCOL_VERSION, 0, 0]),
+            Err(NyxError::MalformedPacket(_))
+        ));
+
+        let mut feedback = Packet::Feedback(Feedback {
+            object_id: 7,
+            next_symbol_size: 800,
+            digest: [3; FEEDBACK_DIGEST_SIZE],
+        })
+        .serialize();
+        assert_eq!(
+            Packet::parse(&feedback).unwrap(),
+            Packet::Feedback(Feedback {
+                object_id: 7,
+                next_symbol_size: 800,
+                digest: [3; FEEDBACK_DIGEST_SIZE],
+            })
+        );
+
+        feedback[0] = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            Packet::parse(&feedback),
+            Err(NyxError::VersionMismatch { found, .. }) if found == PROTOCOL_VERSION + 1
+        ));
+
+        let handshake = Packet::HandshakeInit(Handshake {
+            session_id: 42,
+            message: vec![9; 96],
+        });
+        let mut datagram = handshake.serialize();
+        assert!(is_handshake(&datagram));
+        assert_eq!(Packet::parse(&datagram).unwrap(), handshake);
+        datagram.truncate(HANDSHAKE_OVERHEAD - 1);
+        assert!(matches!(
+            Packet::parse(&datagram),
+            Err(NyxError::MalformedPacket(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive_object() {
+        let mut receiver = Receiver::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        let receive = tokio::spawn(async move { receiver.recv_object().await });
+        let mut sender = Sender::connect(receiver_addr).await.unwrap();
+
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+
+        let delivery = sender.send_object(&data).await.unwrap();
+        let object = receive.await.unwrap().unwrap();
+
+        assert_eq!(object.data, data);
+        assert_eq!(object.object_id, delivery.object_id);
+        assert_eq!(sender.symbol_size(), delivery.next_symbol_size);
+    }
+
+    #[tokio::test]
+    async fn test_empty_objects_are_rejected() {
+        let network = MemoryNetwork::new();
+        let receiver_addr = "10.0.0.1:9000".parse().unwrap();
+        let transport = network.bind("10.0.0.2:9000".parse().unwrap()).unwrap();
+        let mut sender =
+            Sender::with_transport(Box::new(transport), receiver_addr, SenderConfig::default())
+                .unwrap();
+
+        assert!(matches!(
+            sender.send_object(&[]).await,
+            Err(NyxError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_receiver_drops_invalid_datagrams() {
+        // The attacker skips the hello; this is about what happens to datagrams
+        // from peers that got past address validation.
+        let mut receiver = Receiver::bind_with_config(
+            "127.0.0.1:0".parse().unwrap(),
+            ReceiverConfig {
+                validate_addresses: false,
+                ..ReceiverConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        let attacker = UdpLiteTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let packets = Encoder::new(&data, oti).get_encoded_packets(MIN_PACKETS);
+        let valid = Packet::Data(DataPacket {
+            object_id: 1,
+            oti,
+            digest: object_digest(&data),
+            packet: packets[0].clone(),
+        })
+        .serialize();
+
+        let mut zero_symbol_size = valid.clone();
+        zero_symbol_size[16] = 0;
+        zero_symbol_size[17] = 0;
+        let mut wrong_version = valid.clone();
+        wrong_version[0] = PROTOCOL_VERSION + 1;
+        let invalid: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![PROTOCOL_VERSION],
+            valid[..valid.len() - 1].to_vec(),
+            valid[..20].to_vec(),
+            zero_symbol_size,
+            wrong_version,
+        ];
+        for datagram in &invalid {
+            attacker.send_to(datagram, receiver_addr).await.unwrap();
+        }
+        for packet in packets {
+            let datagram = Packet::Data(DataPacket {
+                object_id: 1,
+                oti,
+                digest: object_digest(&data),
+                packet,
+            })
+            .serialize();
+            attacker.send_to(&datagram, receiver_addr).await.unwrap();
+        }
+
+        let object = receiver.recv_object().await.unwrap();
+        assert_eq!(object.data, data);
+        let stats = receiver.stats();
+        // Damage past the fixed-size prefix shows up as a checksum failure.
+        assert_eq!(stats.malformed_packets, 3);
+        assert_eq!(stats.corrupted_packets, 2);
+        assert_eq!(stats.version_mismatches, 1);
+        assert_eq!(stats.objects_decoded, 1);
+    }
+
+    #[test]
+    fn test_reassembler_survives_mutated_datagrams() {
+        let peer = "127.0.0.1:55555".parse().unwrap();
+        let mut rng = StdRng::seed_from_u64(0x6e7978);
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        rng.fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let datagrams: Vec<Vec<u8>> = Encoder::new(&data, oti)
+            .get_encoded_packets(MIN_PACKETS)
+            .into_iter()
+            .map(|packet| {
+                Packet::Data(DataPacket {
+                    object_id: 1,
+                    oti,
+                    digest: object_digest(&data),
+                    packet,
+                })
+                .serialize()
+            })
+            .collect();
+
+        let mut reassembler = Reassembler::new();
+        for round in 0..2000 {
+            let mut datagram = datagrams[round % datagrams.len()].clone();
+            match rng.gen_range(0..3) {
+                0 => datagram.truncate(rng.gen_range(0..datagram.len())),
+                1 => {
+                    for _ in 0..rng.gen_range(1..4) {
+                        let i = rng.gen_range(0..datagram.len());
+                        datagram[i] = rng.gen();
+                    }
+                }
+                _ => datagram.extend((0..rng.gen_range(1..8)).map(|_| rng.gen::<u8>())),
+            }
+            reassembler.handle_datagram(peer, &datagram);
+        }
+        assert_eq!(reassembler.stats().datagrams_received, 2000);
+    }
+
+    #[test]
+    fn test_corrupted_symbols_are_erasures() {
+        let peer = "127.0.0.1:55555".parse().unwrap();
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let mut datagrams: Vec<Vec<u8>> = Encoder::new(&data, oti)
+            .get_encoded_packets(MIN_PACKETS)
+            .into_iter()
+            .map(|packet| {
+                Packet::Data(DataPacket {
+                    object_id: 1,
+                    oti,
+                    digest: object_digest(&data),
+                    packet,
+                })
+                .serialize()
+            })
+            .collect();
+
+        // The first symbol is lost, the next two have a byte flipped in the
+        // symbol, which UDP-Lite would have delivered anyway.
+        for datagram in &mut datagrams[1..3] {
+            let last = datagram.len() - 1;
+            datagram[last] ^= 0x01;
+            assert!(matches!(
+                Packet::parse(datagram),
+                Err(NyxError::ChecksumMismatch { object_id: 1 })
+            ));
+        }
+        let mut reassembler = Reassembler::new();
+        let object = datagrams[1..]
+            .iter()
+            .find_map(
+                |datagram| match reassembler.handle_datagram(peer, datagram) {
+                    Some(ReceiveEvent::Object(object)) => Some(object),
+                    _ => None,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(object.data, data);
+        assert_eq!(object.packets_corrupted, 2);
+        assert_eq!(object.packets_lost, 1);
+        let stats = reassembler.stats();
+        assert_eq!(stats.corrupted_packets, 2);
+        assert_eq!(stats.lost_packets, 1);
+        assert_eq!(stats.malformed_packets, 0);
+    }
+
+    #[test]
+    fn test_objects_are_checked_against_their_digest() {
+        let peer = "127.0.0.1:55555".parse().unwrap();
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        // Symbols that all check out individually, announcing a digest of
+        // different bytes than they encode.
+        let wrong_digest = object_digest(&data[1..]);
+        let mut reassembler = Reassembler::new();
+        let events: Vec<ReceiveEvent> = Encoder::new(&data, oti)
+            .get_encoded_packets(MIN_PACKETS)
+            .into_iter()
+            .filter_map(|packet| {
+                let datagram = Packet::Data(DataPacket {
+                    object_id: 1,
+                    oti,
+                    digest: wrong_digest,
+                    packet,
+                })
+                .serialize();
+                reassembler.handle_datagram(peer, &datagram)
+            })
+            .collect();
+
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, ReceiveEvent::IntegrityFailure { object_id: 1, .. })));
+        let stats = reassembler.stats();
+        assert_eq!(stats.integrity_failures, events.len() as u64);
+        assert_eq!(stats.objects_decoded, 0);
+    }
+
+    #[tokio::test]
+    async fn test_address_validation() {
+        let peer = "10.0.0.2:40000".parse().unwrap();
+        let spoofer = "10.0.0.3:40000".parse().unwrap();
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let datagrams: Vec<Vec<u8>> = Encoder::new(&data, oti)
+            .get_encoded_packets(MIN_PACKETS)
+            .into_iter()
+            .map(|packet| {
+                Packet::Data(DataPacket {
+                    object_id: 1,
+                    oti,
+                    digest: object_digest(&data),
+                    packet,
+                })
+                .serialize()
+            })
+            .collect();
+        let hello = |cookie| Packet::Hello(Hello { nonce: 9, cookie }).serialize();
+
+        let mut reassembler = Reassembler::new();
+        reassembler.require_address_validation();
+        assert!(reassembler.handle_datagram(peer, &datagrams[0]).is_none());
+        let cookie = match reassembler.handle_datagram(peer, &hello([0; COOKIE_SIZE])) {
+            Some(ReceiveEvent::Retry(retry)) => retry.cookie,
+            event => panic!("expected a retry, got {:?}", event),
+        };
+        // A cookie is good only for the address it was sent to.
+        assert!(matches!(
+            reassembler.handle_datagram(spoofer, &hello(cookie)),
+            Some(ReceiveEvent::Retry(_))
+        ));
+        assert!(matches!(
+            reassembler.handle_datagram(peer, &hello(cookie)),
+            Some(ReceiveEvent::Hello(_))
+        ));
+        assert!(reassembler.is_validated(peer));
+        assert!(!reassembler.is_validated(spoofer));
+        let object = datagrams
+            .iter()
+            .find_map(
+                |datagram| match reassembler.handle_datagram(peer, datagram) {
+                    Some(ReceiveEvent::Object(object)) => Some(object),
+                    _ => None,
+                },
+            )
+            .unwrap();
+        assert_eq!(object.data, data);
+        assert_eq!(reassembler.stats().unvalidated_packets, 1);
+
+        // A receiver that may not reply with more than it received sends no
+        // retry to an address it cannot trust, so the sender never gets in.
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                amplification_limit: 0,
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let _ = tokio::time::timeout(Duration::from_millis(200), receiver.recv_object()).await;
+            receiver.stats()
+        });
+        let mut sender = Sender::with_transport(
+            Box::new(network.bind(peer).unwrap()),
+            receiver_addr,
+            SenderConfig {
+                handshake_timeout: Duration::from_millis(50),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(sender.handshake().await, Err(NyxError::Timeout)));
+        let stats = receive.await.unwrap();
+        assert_eq!(stats.hellos_received, 3);
+        assert_eq!(stats.withheld_replies, 3);
+    }
+
+    #[test]
+    fn test_receiver_limits() {
+        let alice = "10.0.0.2:40000".parse().unwrap();
+        let bob = "10.0.0.3:40000".parse().unwrap();
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let object = |object_id| -> (Vec<u8>, Vec<Vec<u8>>) {
+            let mut data = vec![0u8; DATA_SIZE as usize];
+            thread_rng().fill(&mut data[..]);
+            let datagrams = Encoder::new(&data, oti)
+                .get_encoded_packets(MIN_PACKETS)
+                .into_iter()
+                .map(|packet| {
+                    Packet::Data(DataPacket {
+                        object_id,
+                        oti,
+                        digest: object_digest(&data),
+                        packet,
+                    })
+                    .serialize()
+                })
+                .collect();
+            (data, datagrams)
+        };
+        let decode = |reassembler: &mut Reassembler, peer, datagrams: &[Vec<u8>]| {
+            datagrams.iter().find_map(|datagram| {
+                match reassembler.handle_datagram(peer, datagram) {
+                    Some(ReceiveEvent::Object(object)) => Some(object.data),
+                    _ => None,
+                }
+            })
+        };
+
+        // A peer sending faster than its rate loses the excess, and no one
+        // else is held back by it.
+        let (_, datagrams) = object(1);
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_packets_per_second: 2,
+                ..Limits::default()
+            },
+        );
+        for datagram in &datagrams {
+            reassembler.handle_datagram(alice, datagram);
+        }
+        reassembler.handle_datagram(bob, &datagrams[0]);
+        assert_eq!(
+            reassembler.stats().rate_limited_packets,
+            datagrams.len() as u64 - 2
+        );
+
+        // A new peer is turned away while the session table is full, and gets
+        // in once an idle session can make way.
+        let (data, datagrams) = object(1);
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_sessions: 1,
+                idle_timeout: Duration::from_millis(20),
+                ..Limits::default()
+            },
+        );
+        reassembler.handle_datagram(alice, &datagrams[0]);
+        assert!(decode(&mut reassembler, bob, &datagrams).is_none());
+        assert_eq!(
+            reassembler.stats().session_limited_packets,
+            datagrams.len() as u64
+        );
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(decode(&mut reassembler, bob, &datagrams), Some(data));
+        assert_eq!(reassembler.stats().evicted_sessions, 1);
+
+        // Validated addresses count against the session limit too, and have to
+        // validate again once their session is evicted.
+        let hello = |cookie| Packet::Hello(Hello { nonce: 9, cookie }).serialize();
+        let validate = |reassembler: &mut Reassembler, peer| {
+            let cookie = match reassembler.handle_datagram(peer, &hello([0; COOKIE_SIZE])) {
+                Some(ReceiveEvent::Retry(retry)) => retry.cookie,
+                event => panic!("expected a retry, got {:?}", event),
+            };
+            reassembler.handle_datagram(peer, &hello(cookie))
+        };
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_sessions: 1,
+                idle_timeout: Duration::from_millis(20),
+                ..Limits::default()
+            },
+        );
+        reassembler.require_address_validation();
+        assert!(matches!(
+            validate(&mut reassembler, alice),
+            Some(ReceiveEvent::Hello(_))
+        ));
+        assert!(validate(&mut reassembler, bob).is_none());
+        assert!(!reassembler.is_validated(bob));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(
+            validate(&mut reassembler, bob),
+            Some(ReceiveEvent::Hello(_))
+        ));
+        assert!(reassembler.is_validated(bob));
+        assert!(!reassembler.is_validated(alice));
+        assert_eq!(reassembler.stats().evicted_sessions, 1);
+
+        // With room for one decoder, a second peer's object does not fit while
+        // the first is pending. A peer's newer object replaces its older one.
+        let (_, first) = object(1);
+        let (data, second) = object(2);
+        let (bobs_data, bobs) = object(1);
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_objects_per_session: 1,
+                max_decoder_memory: decoder_cost(&oti),
+                ..Limits::default()
+            },
+        );
+        reassembler.handle_datagram(alice, &first[0]);
+        reassembler.handle_datagram(bob, &bobs[0]);
+        assert_eq!(reassembler.stats().over_budget_packets, 1);
+        assert_eq!(decode(&mut reassembler, alice, &second), Some(data));
+        assert_eq!(reassembler.stats().evicted_objects, 1);
+        assert_eq!(decode(&mut reassembler, bob, &bobs), Some(bobs_data));
+        assert_eq!(reassembler.stats().over_budget_packets, 1);
+
+        // A newer object too large for the budget does not cost the peer the
+        // object it has pending.
+        let (data, first) = object(1);
+        let big_oti = ObjectTransmissionInformation::with_defaults(4 * DATA_SIZE, SYMBOL_SIZE);
+        let big = Packet::Data(DataPacket {
+            object_id: 2,
+            oti: big_oti,
+            digest: object_digest(&[0; 4 * DATA_SIZE as usize]),
+            packet: Encoder::new(&[0; 4 * DATA_SIZE as usize], big_oti).get_encoded_packets(0)[0]
+                .clone(),
+        })
+        .serialize();
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_objects_per_session: 1,
+                max_decoder_memory: decoder_cost(&oti),
+                ..Limits::default()
+            },
+        );
+        reassembler.handle_datagram(alice, &first[0]);
+        reassembler.handle_datagram(alice, &big);
+        assert_eq!(reassembler.stats().over_budget_packets, 1);
+        assert_eq!(reassembler.stats().evicted_objects, 0);
+        assert_eq!(decode(&mut reassembler, alice, &first[1..]), Some(data));
+
+        // A peer allowed no objects at all gets none decoded.
+        let (_, datagrams) = object(1);
+        let mut reassembler = Reassembler::with_limits(
+            MAX_SYMBOL_SIZE,
+            Limits {
+                max_objects_per_session: 0,
+                ..Limits::default()
+            },
+        );
+        assert!(decode(&mut reassembler, alice, &datagrams).is_none());
+        assert_eq!(
+            reassembler.stats().over_budget_packets,
+            datagrams.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_access_lists() {
+        let key = [0x6a; 32];
+        let acl: Acl = format!(
+            "# Office and VPN, less one host\n\
+             allow 10.0.0.0/8\n\
+             allow fd00::/8   # VPN\n\
+             deny 10.0.0.66\n\
+             \n\
+             deny psk guests\n\
+             limit key {} max_sessions=1 max_packets_per_second=2\n",
+            "6a".repeat(32)
+        )
+        .parse()
+        .unwrap();
+        let permits = |addr: &str| acl.permits_address(addr.parse().unwrap());
+        assert!(permits("10.1.2.3:1000"));
+        assert!(permits("[fd12::1]:1000"));
+        assert!(permits("[::ffff:10.0.0.1]:1000"));
+        assert!(!permits("10.0.0.66:1000"));
+        assert!(!permits("192.0.2.1:1000"));
+        assert!(!permits("[fe80::1]:1000"));
+        assert!(acl.permits_identity(None));
+        assert!(acl.permits_identity(Some(&Identity::Key(key))));
+        assert!(!acl.permits_identity(Some(&Identity::Psk("guests".into()))));
+        let limits = acl.limits_for(&Identity::Key(key)).unwrap();
+        assert_eq!(limits.max_sessions, 1);
+        assert_eq!(limits.max_objects_per_session, 4);
+        assert_eq!(
+            "allow 10.0.0.0/8\nallow 10.0.0.0/33".parse::<Acl>(),
+            Err("line 2: not a prefix length for 10.0.0.0: 33".to_string())
+        );
+        assert!("permit 10.0.0.0/8".parse::<Acl>().is_err());
+        assert!("limit key 6a max_sessions=1".parse::<Acl>().is_err());
+        assert!("allow psk".parse::<Acl>().is_err());
+
+        // Refused addresses are dropped before anything else, and an identity
+        // with limits of its own is held to them across all its addresses.
+        let oti = ObjectTransmissionInformation::with_defaults(DATA_SIZE, SYMBOL_SIZE);
+        let data = vec![1u8; DATA_SIZE as usize];
+        let datagram = Packet::Data(DataPacket {
+            object_id: 1,
+            oti,
+            digest: object_digest(&data),
+            packet: Encoder::new(&data, oti).get_encoded_packets(0).remove(0),
+        })
+        .serialize();
+        let mut reassembler = Reassembler::new();
+        reassembler.restrict(acl);
+        let first = "10.0.0.2:40000".parse().unwrap();
+        let second = "10.0.0.3:40000".parse().unwrap();
+        reassembler.handle_datagram("10.0.0.66:40000".parse().unwrap(), &datagram);
+        assert_eq!(reassembler.stats().denied_packets, 1);
+        let identity = Identity::Key(key);
+        for _ in 0..3 {
+            reassembler.handle_identified(first, Some(&identity), &datagram);
+        }
+        reassembler.handle_identified(second, Some(&identity), &datagram);
+        reassembler.handle_identified(second, None, &datagram);
+        let stats = reassembler.stats();
+        assert_eq!(stats.rate_limited_packets, 1);
+        assert_eq!(stats.session_limited_packets, 1);
+        assert_eq!(stats.datagrams_received, 6);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_datagrams_are_counted() {
+        let mut receiver = Receiver::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        let attacker = UdpLiteTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        attacker
+            .send_to(&[PROTOCOL_VERSION; 5000], receiver_addr)
+            .await
+            .unwrap();
+
+        let receive = tokio::spawn(async move {
+            let object = receiver.recv_object().await.unwrap();
+            (object, receiver.stats())
+        });
+        let mut sender = Sender::connect(receiver_addr).await.unwrap();
+        let data = vec![7u8; DATA_SIZE as usize];
+        sender.send_object(&data).await.unwrap();
+        let (object, stats) = receive.await.unwrap();
+
+        assert_eq!(object.data, data);
+        assert_eq!(stats.truncated_packets, 1);
+        assert_eq!(stats.malformed_packets, 0);
+    }
+
+    #[tokio::test]
+    async fn test_jumbo_symbols() {
+        let mut receiver = Receiver::bind_with_config(
+            "127.0.0.1:0".parse().unwrap(),
+            ReceiverConfig {
+                max_symbol_size: MAX_JUMBO_SYMBOL_SIZE,
+                ..ReceiverConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        tokio::spawn(async move { while receiver.recv_object().await.is_ok() {} });
+        let mut sender = Sender::connect_with_config(
+            receiver_addr,
+            SenderConfig {
+                max_symbol_size: MAX_JUMBO_SYMBOL_SIZE,
+                ..SenderConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut data = vec![0u8; 20_000];
+        thread_rng().fill(&mut data[..]);
+        // The first object goes out with the minimum symbol size; the feedback
+        // lets the next one use jumbo symbols.
+        sender.send_object(&data).await.unwrap();
+        assert!(sender.symbol_size() > MAX_SYMBOL_SIZE);
+        sender.send_object(&data).await.unwrap();
+    }
+
+    #[test]
+    fn test_pmtu_search_converges_on_path_mtu() {
+        let path_mtu = 1400;
+        let mut search = PmtuSearch::new(MAX_SYMBOL_SIZE + DATA_OVERHEAD as u16);
+        let mut probes = 0;
+        while let Some(size) = search.next_probe() {
+            probes += 1;
+            if size <= path_mtu {
+                search.on_ack(size);
+            } else {
+                search.on_timeout();
+            }
+        }
+        assert_eq!(search.state(), PmtuState::SearchComplete);
+        assert!(search.plpmtu() <= path_mtu && search.plpmtu() > path_mtu - 16);
+        assert!(probes < 40, "search took {} probes", probes);
+
+        // Nothing answers: stay at the base size.
+        let mut search = PmtuSearch::new(MAX_SYMBOL_SIZE + DATA_OVERHEAD as u16);
+        while search.next_probe().is_some() {
+            search.on_timeout();
+        }
+        assert_eq!(search.state(), PmtuState::Error);
+        assert_eq!(search.plpmtu(), BASE_PLPMTU);
+    }
+
+    #[tokio::test]
+    async fn test_discover_path_mtu_on_loopback() {
+        let mut receiver = Receiver::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        tokio::spawn(async move { while receiver.recv_object().await.is_ok() {} });
+        let mut sender = Sender::connect(receiver_addr).await.unwrap();
+
+        let path_mtu = sender.discover_path_mtu().await.unwrap();
+        assert_eq!(path_mtu, MAX_SYMBOL_SIZE + DATA_OVERHEAD as u16);
+        assert_eq!(sender.max_symbol_size(), MAX_SYMBOL_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_transports() {
+        assert_eq!("UDP".parse::<TransportKind>(), Ok(TransportKind::Udp));
+        assert_eq!(
+            "udp-lite".parse::<TransportKind>(),
+            Ok(TransportKind::UdpLite)
+        );
+        assert!("tcp".parse::<TransportKind>().is_err());
+
+        let network = MemoryNetwork::new();
+        let server: Box<dyn DatagramTransport> =
+            Box::new(network.bind("10.0.0.1:55555".parse().unwrap()).unwrap());
+        let client: Box<dyn DatagramTransport> =
+            Box::new(network.bind("10.0.0.2:0".parse().unwrap()).unwrap());
+        assert!(network.bind("10.0.0.1:55555".parse().unwrap()).is_err());
+
+        for (server, client) in [
+            (server, client),
+            (
+                crate::transport::bind(TransportKind::Udp, "127.0.0.1:0".parse().unwrap()).unwrap(),
+                crate::transport::bind(TransportKind::Udp, "127.0.0.1:0".parse().unwrap()).unwrap(),
+            ),
+        ] {
+            let kind = server.kind();
+            let mut receiver = Receiver::with_transport(server, ReceiverConfig::default());
+            let mut sender = Sender::with_transport(
+                client,
+                receiver.local_addr().unwrap(),
+                SenderConfig::default(),
+            )
+            .unwrap();
+
+            let mut data = vec![0u8; DATA_SIZE as usize];
+            thread_rng().fill(&mut data[..]);
+            let receive = tokio::spawn(async move { receiver.recv_object().await });
+            // The receiver validates addresses, so data only gets through after
+            // a hello.
+            sender.handshake().await.unwrap();
+            sender.send_object(&data).await.unwrap();
+            assert_eq!(receive.await.unwrap().unwrap().data, data, "{}", kind);
+            assert_eq!(sender.transport_kind(), kind);
+        }
+    }
+
+    /// Runs `receiver` for the rest of the test, passing on each object it
+    /// decodes along with its stats at the time. Unlike stopping after the last
+    /// object, this keeps acknowledging resends of objects whose feedback was
+    /// lost.
+    fn serve(mut receiver: Receiver) -> mpsc::UnboundedReceiver<(Vec<u8>, ReceiverStats)> {
+        let (decoded, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok(object) = receiver.recv_object().await {
+                if decoded.send((object.data, receiver.stats())).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// The next `count` objects from [`serve`], leaving out those decoded again
+    /// when resent, and the receiver's stats after the last.
+    async fn collect(
+        decoded: &mut mpsc::UnboundedReceiver<(Vec<u8>, ReceiverStats)>,
+        count: usize,
+    ) -> (Vec<Vec<u8>>, ReceiverStats) {
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+        let mut stats = ReceiverStats::default();
+        while objects.len() < count {
+            let (data, latest) = decoded.recv().await.expect("receiver running");
+            if objects.last() != Some(&data) {
+                objects.push(data);
+            }
+            stats = latest;
+        }
+        (objects, stats)
+    }
+
+    /// A transport that cannot send to one address, as if the route to it
+    /// had gone.
+    struct Unreachable {
+        inner: crate::transport::MemoryTransport,
+        unreachable: std::net::SocketAddr,
+    }
+
+    impl DatagramTransport for Unreachable {
+        fn kind(&self) -> TransportKind {
+            self.inner.kind()
+        }
+
+        fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+            self.inner.local_addr()
+        }
+
+        fn send_to<'a>(
+            &'a self,
+            buf: &'a [u8],
+            target: std::net::SocketAddr,
+        ) -> futures_util::future::BoxFuture<'a, std::io::Result<usize>> {
+            if target == self.unreachable {
+                let error = std::io::Error::new(std::io::ErrorKind::HostUnreachable, "no route");
+                return Box::pin(futures_util::future::ready(Err(error)));
+            }
+            self.inner.send_to(buf, target)
+        }
+
+        fn recv_from<'a>(
+            &'a self,
+            buf: &'a mut [u8],
+        ) -> futures_util::future::BoxFuture<'a, std::io::Result<(usize, std::net::SocketAddr)>>
+        {
+            self.inner.recv_from(buf)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_replies() {
+        // Feedback that cannot reach one peer is counted, and the object
+        // still delivered; other peers are served as before.
+        let network = MemoryNetwork::new();
+        let unreachable = "10.0.0.2:40000".parse().unwrap();
+        let receiver = Receiver::with_transport(
+            Box::new(Unreachable {
+                inner: network.bind("10.0.0.1:55555".parse().unwrap()).unwrap(),
+                unreachable,
+            }),
+            ReceiverConfig {
+                validate_addresses: false,
+                ..ReceiverConfig::default()
+            },
+        );
+        let receiver_addr = receiver.local_addr().unwrap();
+        let mut decoded = serve(receiver);
+        let config = SenderConfig {
+            timeout: Duration::from_millis(100),
+            ..SenderConfig::default()
+        };
+        let mut objects = vec![vec![0u8; DATA_SIZE as usize]; 2];
+        for object in &mut objects {
+            thread_rng().fill(&mut object[..]);
+        }
+
+        let mut stranded = Sender::with_transport(
+            Box::new(network.bind(unreachable).unwrap()),
+            receiver_addr,
+            config.clone(),
+        )
+        .unwrap();
+        assert!(matches!(
+            stranded.send_object(&objects[0]).await,
+            Err(NyxError::Timeout)
+        ));
+        let mut sender = Sender::with_transport(
+            Box::new(network.bind("10.0.0.3:40000".parse().unwrap()).unwrap()),
+            receiver_addr,
+            config,
+        )
+        .unwrap();
+        sender.send_object(&objects[1]).await.unwrap();
+        let (received, stats) = collect(&mut decoded, 2).await;
+        assert_eq!(received, objects);
+        assert!(stats.failed_replies >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_simulated_link() {
+        let link = LinkConfig {
+            loss: LossModel::Bernoulli(0.1),
+            delay: Duration::from_millis(5),
+            jitter: Duration::from_millis(5),
+            reorder: 0.1,
+            duplicate: 0.05,
+            corrupt: 0.05,
+            bandwidth: Some(100_000_000),
+            seed: 7,
+            ..LinkConfig::default()
+        };
+
+        // The protocol gets objects across a bad link both ways.
+        let network = MemoryNetwork::new();
+        let server = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.1:55555".parse().unwrap()).unwrap()),
+            LinkConfig {
+                seed: 8,
+                ..link.clone()
+            },
+        );
+        let client = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.2:0".parse().unwrap()).unwrap()),
+            link.clone(),
+        );
+        let receiver = Receiver::with_transport(Box::new(server), ReceiverConfig::default());
+        let mut sender = Sender::with_transport(
+            Box::new(client),
+            receiver.local_addr().unwrap(),
+            SenderConfig {
+                timeout: Duration::from_millis(200),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        let objects: Vec<Vec<u8>> = (0..4)
+            .map(|_| {
+                let mut data = vec![0u8; 4 * DATA_SIZE as usize];
+                thread_rng().fill(&mut data[..]);
+                data
+            })
+            .collect();
+        let mut decoded = serve(receiver);
+        sender.handshake().await.unwrap();
+        for data in &objects {
+            // Like the client, resend until the receiver acknowledges.
+            while let Err(e) = sender.send_object(data).await {
+                assert!(matches!(e, NyxError::Timeout), "{}", e);
+            }
+        }
+        assert_eq!(collect(&mut decoded, objects.len()).await.0, objects);
+
+        // The same seed and traffic make the same choices.
+        let run = || async {
+            let network = MemoryNetwork::new();
+            let sink = network.bind("10.0.0.1:1".parse().unwrap()).unwrap();
+            let source = SimulatedTransport::new(
+                Box::new(network.bind("10.0.0.2:1".parse().unwrap()).unwrap()),
+                link.clone(),
+            );
+            for i in 0..200u32 {
+                source
+                    .send_to(&i.to_be_bytes(), sink.local_addr().unwrap())
+                    .await
+                    .unwrap();
+            }
+            let stats = source.stats();
+            drop(source);
+            let mut arrived = Vec::new();
+            let mut buf = [0u8; 4];
+            while let Ok(Ok((len, _))) =
+                tokio::time::timeout(Duration::from_millis(100), sink.recv_from(&mut buf)).await
+            {
+                arrived.push(buf[..len].to_vec());
+            }
+            (stats, arrived)
+        };
+        let (stats, arrived) = run().await;
+        assert_eq!((stats.sent, stats.bytes), (200, 800));
+        assert!(
+            stats.lost > 0 && stats.reordered > 0 && stats.duplicated > 0 && stats.corrupted > 0
+        );
+        assert_eq!(
+            arrived.len() as u64,
+            stats.sent - stats.lost - stats.overflowed + stats.duplicated
+        );
+        assert_eq!(run().await.0, stats);
+
+        // Probabilities that are not numbers count as 0 rather than panic.
+        let network = MemoryNetwork::new();
+        let sink = network.bind("10.0.0.1:2".parse().unwrap()).unwrap();
+        let source = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.2:2".parse().unwrap()).unwrap()),
+            LinkConfig {
+                loss: LossModel::Bernoulli(f64::NAN),
+                reorder: f64::NAN,
+                duplicate: f64::INFINITY,
+                corrupt: f64::NEG_INFINITY,
+                ..LinkConfig::default()
+            },
+        );
+        source
+            .send_to(b"ping", sink.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut buf = [0u8; 4];
+        sink.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        assert_eq!(
+            source.stats(),
+            LinkStats {
+                sent: 1,
+                bytes: 4,
+                ..LinkStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_loss_models() {
+        // Loss and mean burst length of 100k datagrams under a model.
+        let measure = |model: &LossModel| {
+            let mut process = LossProcess::new(model.clone());
+            let mut rng = StdRng::seed_from_u64(1);
+            let (mut lost, mut bursts, mut previous) = (0, 0, false);
+            for _ in 0..100_000 {
+                let lose = process.lose(&mut rng);
+                lost += lose as u32;
+                bursts += (lose && !previous) as u32;
+                previous = lose;
+            }
+            (lost as f64 / 100_000.0, lost as f64 / bursts.max(1) as f64)
+        };
+
+        let (rate, burst) = measure(&LossModel::Bernoulli(0.1));
+        assert!((rate - 0.1).abs() < 0.01 && burst < 1.5);
+        let bursty = LossModel::bursty(0.1, 8.0);
+        assert!((bursty.loss_rate() - 0.1).abs() < 1e-9);
+        let (rate, burst) = measure(&bursty);
+        assert!((rate - 0.1).abs() < 0.02, "{}", rate);
+        assert!((6.0..10.0).contains(&burst), "{}", burst);
+
+        // A three-state chain with a lossless, a lossy and a dead state.
+        let markov: LossModel = "markov:0,0.5,1;0.9,0.1,0;0.2,0.7,0.1;0,0.5,0.5"
+            .parse()
+            .unwrap();
+        let (rate, _) = measure(&markov);
+        assert!((rate - markov.loss_rate()).abs() < 0.02);
+
+        for text in [
+            "0.25",
+            "ge:0.02,0.2,0.001,0.9",
+            "markov:0,1;0.9,0.1;0.5,0.5",
+        ] {
+            let model: LossModel = text.parse().unwrap();
+            assert_eq!(model.to_string(), text);
+            assert_eq!(model.to_string().parse::<LossModel>(), Ok(model));
+        }
+        assert_eq!(
+            "burst:0.5,2".parse::<LossModel>(),
+            Ok(LossModel::bursty(0.5, 2.0))
+        );
+        for bad in [
+            "1.5",
+            "-0.1",
+            "ge:0.1",
+            "burst:0.1,0.5",
+            "markov:0,1;1,0",
+            "markov:0,1;0.5,0.4;1,0",
+            "pareto:1",
+        ] {
+            assert!(bad.parse::<LossModel>().is_err(), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_traces() {
+        let csv = "time_ms,delivered,delay_ms\n0,1,30\n10,0,\n20,true,40.5\n30,0,\n";
+        let json = r#"[
+            {"time_ms": 0, "delivered": true, "delay_ms": 30},
+            {"time_ms": 10, "delivered": false},
+            {"time_ms": 20, "delivered": true, "delay_ms": 40.5},
+            {"time_ms": 30, "delivered": false, "delay_ms": null}
+        ]"#;
+        let trace: Trace = csv.parse().unwrap();
+        assert_eq!(json.parse::<Trace>(), Ok(trace.clone()));
+        assert_eq!(trace.to_string().parse::<Trace>(), Ok(trace.clone()));
+        assert_eq!(trace.loss_rate(), 0.5);
+        assert_eq!(trace.duration(), Duration::from_millis(40));
+        // Time replay holds each record until the next, and loops.
+        assert_eq!(trace.at(Duration::from_millis(25)), &trace.records()[2]);
+        assert_eq!(trace.at(Duration::from_millis(45)), &trace.records()[0]);
+        assert_eq!(trace.nth(5), &trace.records()[1]);
+        for bad in [
+            "",
+            "0,maybe,1",
+            "0,1,-5",
+            "10,1,1\n0,1,1",
+            "[{\"time_ms\": 0}]",
+            "[{\"time_ms\": 0, \"delivered\": 2}]",
+            "[{\"time_ms\": \"0\", \"delivered\": true}]",
+            "[]",
+        ] {
+            assert!(bad.parse::<Trace>().is_err(), "{}", bad);
+        }
+        assert_eq!(
+            r#"[{"time_ms": 0, "delivered": "true"}]"#.parse::<Trace>(),
+            Err("record 0: delivered is not true, false, 1 or 0: \"true\"".to_string())
+        );
+        assert!(Trace::new(Vec::new()).is_err());
+
+        // A simulated link replaying the trace packet by packet loses and
+        // delays exactly as recorded.
+        let network = MemoryNetwork::new();
+        let sink = network.bind("10.0.0.1:1".parse().unwrap()).unwrap();
+        let source = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.2:1".parse().unwrap()).unwrap()),
+            LinkConfig {
+                trace: Some(trace),
+                replay: Replay::Packets,
+                ..LinkConfig::default()
+            },
+        );
+        let start = tokio::time::Instant::now();
+        for i in 0..8u8 {
+            source
+                .send_to(&[i], sink.local_addr().unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(source.stats().lost, 4);
+        let mut buf = [0u8; 1];
+        let mut arrived = Vec::new();
+        while let Ok(Ok(_)) =
+            tokio::time::timeout(Duration::from_millis(200), sink.recv_from(&mut buf)).await
+        {
+            arrived.push((buf[0], start.elapsed()));
+        }
+        assert_eq!(
+            arrived.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            [0, 4, 2, 6]
+        );
+        assert!(arrived[0].1 >= Duration::from_millis(30));
+        assert!(arrived[2].1 >= Duration::from_millis(40));
+    }
+
+    /// Sends `OBJECTS` objects over a bad simulated link seeded from `seed`,
+    /// and reports everything that happened along the way.
+    async fn simulated_run(
+        seed: u64,
+    ) -> (Vec<Option<(u32, u32, Duration)>>, ReceiverStats, Duration) {
+        const OBJECTS: usize = 40;
+        let link = LinkConfig {
+            loss: LossModel::bursty(0.2, 4.0),
+            delay: Duration::from_secs(2),
+            jitter: Duration::from_millis(300),
+            reorder: 0.05,
+            duplicate: 0.02,
+            corrupt: 0.02,
+            seed,
+            ..LinkConfig::default()
+        };
+        let network = MemoryNetwork::new();
+        let server = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.1:55555".parse().unwrap()).unwrap()),
+            LinkConfig {
+                seed: seed + 1,
+                ..link.clone()
+            },
+        );
+        let client = SimulatedTransport::new(
+            Box::new(network.bind("10.0.0.2:40000".parse().unwrap()).unwrap()),
+            link,
+        );
+        let receiver = Receiver::with_transport(Box::new(server), ReceiverConfig::default());
+        let mut sender = Sender::with_transport(
+            Box::new(client),
+            receiver.local_addr().unwrap(),
+            SenderConfig {
+                timeout: Duration::from_secs(6),
+                handshake_timeout: Duration::from_secs(6),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        let mut decoded = serve(receiver);
+
+        let start = tokio::time::Instant::now();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut attempts = Vec::new();
+        while sender.handshake().await.is_err() {}
+        for _ in 0..OBJECTS {
+            let mut data = vec![0u8; 4 * DATA_SIZE as usize];
+            rng.fill(&mut data[..]);
+            loop {
+                match sender.send_object(&data).await {
+                    Ok(delivery) => {
+                        attempts.push(Some((
+                            delivery.object_id,
+                            delivery.packets_sent,
+                            delivery.rtt,
+                        )));
+                        break;
+                    }
+                    Err(NyxError::Timeout) => attempts.push(None),
+                    Err(e) => panic!("seed {}: {}", seed, e),
+                }
+            }
+        }
+        let (_, stats) = collect(&mut decoded, OBJECTS).await;
+        (attempts, stats, start.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deterministic_simulation() {
+        // NYXPSI_SEED replays a single seed, say one a failure was reported
+        // for.
+        let seeds = match std::env::var("NYXPSI_SEED") {
+            Ok(seed) => vec![seed.parse().unwrap()],
+            Err(_) => vec![1, 2, 3],
+        };
+        for seed in seeds {
+            let wall = std::time::Instant::now();
+            let first = simulated_run(seed).await;
+            let second = simulated_run(seed).await;
+            assert_eq!(first, second, "seed {}", seed);
+            // Minutes of simulated transfer take next to no real time.
+            let (attempts, _, elapsed) = first;
+            assert!(
+                elapsed > Duration::from_secs(160),
+                "seed {}: {:?}",
+                seed,
+                elapsed
+            );
+            assert!(wall.elapsed() < elapsed / 10, "seed {}", seed);
+            assert!(attempts.iter().any(Option::is_none), "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_impairment_proxy() {
+        use crate::proxy::{Proxy, ProxyConfig};
+
+        let link: LinkConfig =
+            "loss=burst:0.3,3 delay=5 jitter=2.5 duplicate=0.05 rate=100M seed=3"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            link,
+            LinkConfig {
+                loss: LossModel::bursty(0.3, 3.0),
+                delay: Duration::from_millis(5),
+                jitter: Duration::from_micros(2500),
+                duplicate: 0.05,
+                bandwidth: Some(100_000_000),
+                seed: 3,
+                ..LinkConfig::default()
+            }
+        );
+        for bad in [
+            "loss",
+            "delay=-1",
+            "reorder=2",
+            "rate=0",
+            "rate=fast",
+            "mtu=1500",
+        ] {
+            assert!(bad.parse::<LinkConfig>().is_err(), "{}", bad);
+        }
+
+        // A real receiver and sender on loopback, talking through the proxy.
+        let receiver = Receiver::bind_with_config(
+            "127.0.0.1:0".parse().unwrap(),
+            ReceiverConfig {
+                transports: vec![TransportKind::Udp],
+                ..ReceiverConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+        let proxy = Proxy::bind(ProxyConfig {
+            transports: vec![TransportKind::Udp],
+            listen: "127.0.0.1:0".parse().unwrap(),
+            upstream: receiver.local_addr().unwrap(),
+            up: link.clone(),
+            down: LinkConfig { seed: 4, ..link },
+            ..ProxyConfig::default()
+        })
+        .unwrap();
+        let proxy_addr = proxy.local_addr();
+        let proxy = std::sync::Arc::new(proxy);
+        tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.run().await }
+        });
+        let objects: Vec<Vec<u8>> = (0..3)
+            .map(|_| {
+                let mut data = vec![0u8; 4 * DATA_SIZE as usize];
+                thread_rng().fill(&mut data[..]);
+                data
+            })
+            .collect();
+        let mut decoded = serve(receiver);
+        let config = SenderConfig {
+            transports: vec![TransportKind::Udp],
+            timeout: Duration::from_millis(200),
+            probe_timeout: Duration::from_millis(50),
+            handshake_timeout: Duration::from_millis(100),
+            ..SenderConfig::default()
+        };
+        let mut sender = loop {
+            match Sender::connect_with_config(proxy_addr, config.clone()).await {
+                Ok(sender) => break sender,
+                Err(e) => assert!(matches!(e, NyxError::Timeout), "{}", e),
+            }
+        };
+        for data in &objects {
+            while let Err(e) = sender.send_object(data).await {
+                assert!(matches!(e, NyxError::Timeout), "{}", e);
+            }
+        }
+        assert_eq!(collect(&mut decoded, objects.len()).await.0, objects);
+        let stats = proxy.stats();
+        assert_eq!(stats.clients, 1);
+        assert!(stats.up.lost + stats.down.lost > 0, "{:?}", stats);
+        assert!(stats.up.sent > 0 && stats.down.sent > 0);
+
+        // Once the links are perfect, nothing more is lost.
+        proxy.set_links(LinkConfig::default(), LinkConfig::default());
+        while let Err(e) = sender.send_object(&objects[0]).await {
+            assert!(matches!(e, NyxError::Timeout), "{}", e);
+        }
+        let after = proxy.stats();
+        assert!(after.up.sent > stats.up.sent);
+        assert_eq!(
+            (after.up.lost, after.down.lost),
+            (stats.up.lost, stats.down.lost)
+        );
+
+        // Clients silent for longer than the idle timeout are forgotten, but
+        // what their links did still counts.
+        let proxy = Proxy::bind(ProxyConfig {
+            transports: vec![TransportKind::Udp],
+            listen: "127.0.0.1:0".parse().unwrap(),
+            upstream: "127.0.0.1:9".parse().unwrap(),
+            idle_timeout: Duration::from_millis(50),
+            ..ProxyConfig::default()
+        })
+        .unwrap();
+        let proxy_addr = proxy.local_addr();
+        let proxy = std::sync::Arc::new(proxy);
+        tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.run().await }
+        });
+        for sent in 1..=3 {
+            let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.send_to(b"hello", proxy_addr).await.unwrap();
+            while proxy.stats().up.sent < sent {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            assert_eq!(proxy.stats().clients, 1);
+            tokio::time::sleep(Duration::from_millis(60)).await;
+        }
+    }
+
+    #[test]
+    fn test_bench_report() {
+        let record = |protocol: &str, scenario: &str, millis: u64, success_rate| BenchRecord {
+            protocol: protocol.to_string(),
+            scenario: scenario.to_string(),
+            loss_rate: 0.1,
+            payload_size: 1_000_000,
+            runs: 11,
+            completion_time: Duration::from_micros(millis * 1000 + 250),
+            goodput: 6.08,
+            overhead: Some(0.11),
+            success_rate,
+        };
+        let mut results = BenchResults {
+            records: vec![
+                record("TCP", "0% loss", 3, 1.0),
+                record("nyxpsi (udp)", "0% loss", 613, 1.0),
+                record("TCP", "10% loss", 1858, 0.91),
+                record("nyxpsi (udp)", "trace \"a, b\".csv", 4482, 0.73),
+            ],
+        };
+        results.records[3].overhead = None;
+        results.records[3].payload_size = 64_000;
+
+        // Both formats read back what was written.
+        let csv = results.to_string();
+        assert!(csv.starts_with("protocol,scenario,loss_rate,payload_size,runs,"));
+        assert!(csv.contains("\"trace \"\"a, b\"\".csv\""), "{}", csv);
+        assert_eq!(csv.parse::<BenchResults>().unwrap(), results);
+        assert_eq!(results.to_json().parse::<BenchResults>().unwrap(), results);
+        for bad in [
+            "TCP,0% loss,0,1000,1,5,1,,1,extra",
+            "TCP,0% loss,0,1000,1,-5,1,,1",
+            "TCP,0% loss,0,big,1,5,1,,1",
+            "\"TCP,0% loss,0,1000,1,5,1,,1",
+            "[{\"protocol\": \"TCP\"}]",
+        ] {
+            assert!(bad.parse::<BenchResults>().is_err(), "{}", bad);
+        }
+
+        // A row per protocol, a column per scenario, for one payload size.
+        assert_eq!(results.payload_sizes(), vec![1_000_000, 64_000]);
+        assert_eq!(
+            results.markdown_table(1_000_000),
+            "| Protocol     | 0% loss       | 10% loss    |\n\
+             | ------------ | ------------- | ----------- |\n\
+             | TCP          | 0.003s (100%) | 1.86s (91%) |\n\
+             | nyxpsi (udp) | 0.61s (100%)  | –           |\n"
+        );
+        let svg = results.svg_plot(1_000_000, Metric::CompletionTime);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<title>").count(), 3);
+        assert!(svg.contains("Completion time (s) for 1 MB"));
+        assert!(results
+            .svg_plot(64_000, Metric::Goodput)
+            .contains("trace \"a, b\".csv"));
+        assert_eq!(size_label(64_000), "64 kB");
+    }
+
+    #[tokio::test]
+    async fn test_transport_fallback() {
+        let config = SenderConfig {
+            handshake_timeout: Duration::from_millis(50),
+            ..SenderConfig::default()
+        };
+
+        // A receiver reachable only over UDP: the sender gives up on UDP-Lite.
+        let mut receiver = Receiver::bind_with_config(
+            "127.0.0.1:0".parse().unwrap(),
+            ReceiverConfig {
+                transports: vec![TransportKind::Udp],
+                ..ReceiverConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            receiver.recv_object().await.unwrap();
+            receiver.stats()
+        });
+        let mut sender = Sender::connect_with_config(addr, config.clone())
+            .await
+            .unwrap();
+        assert_eq!(sender.transport_kind(), TransportKind::Udp);
+        sender.send_object(&[7u8; 100]).await.unwrap();
+        // The first hello, and the second echoing the cookie from the retry.
+        assert_eq!(server.await.unwrap().hellos_received, 2);
+
+        // A receiver on both: the sender's preference decides.
+        let mut receiver = Receiver::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            receiver.transport_kinds(),
+            [TransportKind::UdpLite, TransportKind::Udp]
+        );
+        let addr = receiver.local_addr().unwrap();
+        tokio::spawn(async move { while receiver.recv_object().await.is_ok() {} });
+        for preference in [
+            vec![TransportKind::UdpLite, TransportKind::Udp],
+            vec![TransportKind::Udp, TransportKind::UdpLite],
+        ] {
+            let config = SenderConfig {
+                transports: preference.clone(),
+                ..config.clone()
+            };
+            let mut sender = Sender::connect_with_config(addr, config).await.unwrap();
+            assert_eq!(sender.transport_kind(), preference[0]);
+            sender.send_object(&[7u8; 100]).await.unwrap();
+        }
+
+        // Nobody listening at all.
+        let unused = UdpLiteTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = unused.local_addr().unwrap();
+        drop(unused);
+        assert!(matches!(
+            Sender::connect_with_config(addr, config).await,
+            Err(NyxError::Timeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_feedback_is_bound_to_its_object() {
+        use crate::stats::MIN_SYMBOL_SIZE;
+
+        let network = MemoryNetwork::new();
+        let receiver_addr = "10.0.0.1:55555".parse().unwrap();
+        let sender_transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+        let sender_addr = sender_transport.local_addr().unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(sender_transport),
+            receiver_addr,
+            SenderConfig {
+                timeout: Duration::from_millis(100),
+                probe_timeout: Duration::from_millis(1),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        // Nobody answers probes here; get discovery out of the way first.
+        sender.discover_path_mtu().await.unwrap();
+
+        // Feedback for the right object id but another object's digest, as a
+        // stale ack from an earlier session would be, is ignored; the real
+        // thing is not.
+        let object = [7u8; 100];
+        for (object_id, digest) in [(0, object_digest(&[8u8; 100])), (1, object_digest(&object))] {
+            let feedback = Packet::Feedback(Feedback {
+                object_id,
+                next_symbol_size: MIN_SYMBOL_SIZE,
+                digest: feedback_digest(&digest),
+            })
+            .serialize();
+            let network = network.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                network.deliver(feedback, receiver_addr, sender_addr);
+            });
+            let delivered = sender.send_object(&object).await;
+            if object_id == 0 {
+                assert!(matches!(delivered, Err(NyxError::Timeout)));
+            } else {
+                assert_eq!(delivered.unwrap().object_id, 1);
+            }
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_replayed_datagrams_are_rejected() {
+        use crate::crypto::{Psk, Role, SessionKeys, REPLAY_WINDOW};
+
+        let psk = Psk::new(b"correct horse");
+        let mut sender = SessionKeys::new(&psk, 7, Role::Sender);
+        let mut receiver = SessionKeys::new(&psk, 7, Role::Receiver);
+        let feedback = Packet::Feedback(Feedback {
+            object_id: 3,
+            next_symbol_size: 800,
+            digest: [3; FEEDBACK_DIGEST_SIZE],
+        })
+        .serialize();
+        let sealed: Vec<_> = (0..REPLAY_WINDOW + 3)
+            .map(|_| receiver.seal(&feedback))
+            .collect();
+
+        // Out of order is fine, twice is not.
+        assert_eq!(sender.open(&sealed[1]).unwrap(), feedback);
+        assert_eq!(sender.open(&sealed[0]).unwrap(), feedback);
+        for replayed in &sealed[..2] {
+            assert!(matches!(
+                sender.open(replayed),
+                Err(NyxError::Replayed { .. })
+            ));
+        }
+
+        // Once the window has moved on, late packets cannot be told from
+        // replays and are refused too.
+        let last = sealed.len() - 1;
+        assert_eq!(sender.open(&sealed[last]).unwrap(), feedback);
+        assert!(matches!(
+            sender.open(&sealed[2]),
+            Err(NyxError::Replayed { packet_number: 2 })
+        ));
+        assert_eq!(sender.open(&sealed[last - 1]).unwrap(), feedback);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_key_updates() {
+        use crate::crypto::{Psk, RekeyPolicy, Role, SessionKeys, KEY_PHASE_BIT};
+
+        let psk = Psk::new(b"correct horse");
+        let rekey = RekeyPolicy {
+            max_packets: 4,
+            ..RekeyPolicy::default()
+        };
+        let mut sender = SessionKeys::new(&psk, 7, Role::Sender).with_rekey(rekey);
+        let mut receiver = SessionKeys::new(&psk, 7, Role::Receiver).with_rekey(rekey);
+        let packets: Vec<Vec<u8>> = (0..10)
+            .map(|object_id| {
+                Packet::Feedback(Feedback {
+                    object_id,
+                    next_symbol_size: 1000,
+                    digest: [0; FEEDBACK_DIGEST_SIZE],
+                })
+                .serialize()
+            })
+            .collect();
+        let sealed: Vec<Vec<u8>> = packets.iter().map(|packet| sender.seal(packet)).collect();
+        assert_eq!(sender.seal_updates(), 2);
+        let phases: Vec<bool> = sealed
+            .iter()
+            .map(|sealed| sealed[1] & KEY_PHASE_BIT != 0)
+            .collect();
+        assert_eq!(
+            phases,
+            [0, 0, 0, 0, 1, 1, 1, 1, 0, 0].map(|phase| phase == 1)
+        );
+
+        // The phase bit is authenticated with the rest of the header.
+        let mut flipped = sealed[0].clone();
+        flipped[1] ^= KEY_PHASE_BIT;
+        assert!(matches!(
+            receiver.open(&flipped),
+            Err(NyxError::AuthFailure)
+        ));
+        // The last packet of the first phase arrives after the second phase
+        // has begun, and still opens under the key before.
+        for i in [0, 1, 2, 4, 5, 3, 6, 7, 8, 9] {
+            assert_eq!(
+                receiver.open(&sealed[i]).unwrap(),
+                packets[i],
+                "packet {}",
+                i
+            );
+        }
+        assert_eq!(receiver.open_updates(), 2);
+
+        // Sessions rotating keys every few packets in both directions still
+        // carry objects end to end.
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk: Some(psk.clone()),
+                rekey,
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let mut objects = Vec::new();
+            for _ in 0..3 {
+                objects.push(receiver.recv_object().await.unwrap().data);
+            }
+            (objects, receiver.stats())
+        });
+        let sender_transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(sender_transport),
+            receiver_addr,
+            SenderConfig {
+                psk: Some(psk),
+                rekey,
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        sender.handshake().await.unwrap();
+        let mut sent = Vec::new();
+        for _ in 0..3 {
+            let mut data = vec![0u8; DATA_SIZE as usize];
+            thread_rng().fill(&mut data[..]);
+            sender.send_object(&data).await.unwrap();
+            sent.push(data);
+        }
+        let (objects, stats) = receive.await.unwrap();
+        assert_eq!(objects, sent);
+        assert_eq!(stats.auth_failures, 0);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_padding() {
+        use crate::crypto::{
+            Padding, Psk, Role, SessionKeys, PADDED_CONTROL_SIZE, PADDING_TRAILER_SIZE,
+            SEAL_OVERHEAD,
+        };
+        use crate::packet::Probe;
+
+        let psk = Psk::new(b"correct horse");
+        let padding = Some(Padding { bucket: 256 });
+        let mut sender = SessionKeys::new(&psk, 7, Role::Sender).with_padding(padding);
+        let mut receiver = SessionKeys::new(&psk, 7, Role::Receiver);
+        let data = [5u8; 100];
+        let oti = ObjectTransmissionInformation::with_defaults(data.len() as u64, 100);
+        let symbol = Packet::Data(DataPacket {
+            object_id: 1,
+            oti,
+            digest: object_digest(&data),
+            packet: Encoder::new(&data, oti).get_encoded_packets(0).remove(0),
+        })
+        .serialize();
+        let feedback = Packet::Feedback(Feedback {
+            object_id: 1,
+            next_symbol_size: 1000,
+            digest: [0; FEEDBACK_DIGEST_SIZE],
+        })
+        .serialize();
+        let probe = Packet::Probe(Probe { seq: 1, size: 300 }).serialize();
+
+        // Data rounds up to the bucket, or to the cap below it; control packets
+        // all come out the same size; probes keep theirs but for the trailer.
+        let sizes = |sender: &mut SessionKeys, receiver: &mut SessionKeys| {
+            [&symbol, &feedback, &probe].map(|datagram| {
+                let sealed = sender.seal(datagram);
+                assert_eq!(&receiver.open(&sealed).unwrap(), datagram);
+                sealed.len() - SEAL_OVERHEAD
+            })
+        };
+        assert_eq!(
+            sizes(&mut sender, &mut receiver),
+            [256, PADDED_CONTROL_SIZE, 300 + PADDING_TRAILER_SIZE]
+        );
+        sender.set_padding_limit(200);
+        assert_eq!(sizes(&mut sender, &mut receiver)[0], 200);
+
+        // End to end, the cost shows up on both sides.
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk: Some(psk.clone()),
+                padding,
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let object = receiver.recv_object().await.unwrap();
+            (object, receiver.stats())
+        });
+        let sender_transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(sender_transport),
+            receiver_addr,
+            SenderConfig {
+                psk: Some(psk),
+                padding,
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+        sender.handshake().await.unwrap();
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        let delivery = sender.send_object(&data).await.unwrap();
+        let (object, stats) = receive.await.unwrap();
+        assert_eq!(object.data, data);
+        assert!(delivery.padding_bytes > 0);
+        assert!(stats.padding_bytes_received > 0);
+        assert!(stats.padding_bytes_sent > 0);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_sealed_datagrams() {
+        use crate::crypto::{Psk, Role, SessionKeys};
+        use crate::stats::MIN_SYMBOL_SIZE;
+
+        let network = MemoryNetwork::new();
+        let receiver_addr = "10.0.0.1:55555".parse().unwrap();
+        let sender_transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+        let sender_addr = sender_transport.local_addr().unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(sender_transport),
+            receiver_addr,
+            SenderConfig {
+                timeout: Duration::from_millis(100),
+                handshake_timeout: Duration::from_millis(100),
+                psk: Some(Psk::new(b"correct horse")),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+
+        // A receiver with the wrong key drops everything.
+        let receiver_transport = network.bind(receiver_addr).unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk: Some(Psk::new(b"battery staple")),
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let _ = tokio::time::timeout(Duration::from_millis(200), receiver.recv_object()).await;
+            receiver.stats()
+        });
+        assert!(matches!(sender.handshake().await, Err(NyxError::Timeout)));
+        let stats = receive.await.unwrap();
+        assert!(stats.auth_failures > 0);
+        assert_eq!(stats.hellos_received, 0);
+
+        // The right key gets everything through, probes included.
+        let receiver_transport = network.bind(receiver_addr).unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk: Some(Psk::new(b"correct horse")),
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let object = receiver.recv_object().await.unwrap();
+            (object, receiver.stats())
+        });
+        sender.handshake().await.unwrap();
+        assert_eq!(
+            sender.discover_path_mtu().await.unwrap(),
+            sender.max_symbol_size() + (DATA_OVERHEAD + crate::crypto::SEAL_OVERHEAD) as u16
+        );
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        sender.send_object(&data).await.unwrap();
+        let (object, stats) = receive.await.unwrap();
+        assert_eq!(object.data, data);
+        assert_eq!(stats.auth_failures, 0);
+
+        // Once the receiver is gone, feedback spoofed from its address, in the
+        // clear and sealed under another key, must not be taken for the real
+        // thing even when it names the right object.
+        let object = [7u8; 100];
+        let mut forger = SessionKeys::new(&Psk::new(b"battery staple"), 1, Role::Receiver);
+        for object_id in 1..3 {
+            let feedback = Packet::Feedback(Feedback {
+                object_id,
+                next_symbol_size: MIN_SYMBOL_SIZE + 8,
+                digest: feedback_digest(&object_digest(&object)),
+            })
+            .serialize();
+            let forged = if object_id == 1 {
+                feedback
+            } else {
+                forger.seal(&feedback)
+            };
+            let network = network.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                network.deliver(forged, receiver_addr, sender_addr);
+            });
+            assert!(matches!(
+                sender.send_object(&object).await,
+                Err(NyxError::Timeout)
+            ));
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_noise_handshake() {
+        use crate::crypto::{Keypair, NoiseInitiator, NoiseResponder};
+
+        let network = MemoryNetwork::new();
+        let receiver_addr = "10.0.0.1:55555".parse().unwrap();
+        let server = Keypair::generate();
+        let client = Keypair::generate();
+        let receiver_config = ReceiverConfig {
+            noise: Some(NoiseResponder {
+                keypair: server.clone(),
+                authorized: vec![client.public()],
+            }),
+            ..ReceiverConfig::default()
+        };
+        let sender = |keypair: &Keypair, responder: &Keypair| {
+            let transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+            Sender::with_transport(
+                Box::new(transport),
+                receiver_addr,
+                SenderConfig {
+                    handshake_timeout: Duration::from_millis(100),
+                    noise: Some(NoiseInitiator {
+                        keypair: keypair.clone(),
+                        responder: responder.public(),
+                    }),
+                    ..SenderConfig::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // A sender the receiver does not know, and one expecting another
+        // receiver, both go unanswered.
+        let stranger = Keypair::generate();
+        for (keypair, responder) in [(&stranger, &server), (&client, &stranger)] {
+            let receiver_transport = network.bind(receiver_addr).unwrap();
+            let mut receiver =
+                Receiver::with_transport(Box::new(receiver_transport), receiver_config.clone());
+            let receive = tokio::spawn(async move {
+                let _ =
+                    tokio::time::timeout(Duration::from_millis(400), receiver.recv_object()).await;
+                receiver.stats()
+            });
+            let mut sender = sender(keypair, responder);
+            assert!(matches!(sender.handshake().await, Err(NyxError::Timeout)));
+            let stats = receive.await.unwrap();
+            assert_eq!(stats.handshakes_received, 3);
+            assert_eq!(stats.handshake_failures, 3);
+        }
+
+        // The first handshake message is lost because the receiver is not up
+        // yet; the retransmission gets through, and the session it keys carries
+        // the object without an explicit handshake.
+        let mut sender = sender(&client, &server);
+        let receive = tokio::spawn({
+            let network = network.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let receiver_transport = network.bind(receiver_addr).unwrap();
+                let mut receiver =
+                    Receiver::with_transport(Box::new(receiver_transport), receiver_config);
+                let object = receiver.recv_object().await.unwrap();
+                (object, receiver.stats())
+            }
+        });
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        sender.send_object(&data).await.unwrap();
+        let (object, stats) = receive.await.unwrap();
+        assert_eq!(object.data, data);
+        assert_eq!(stats.handshakes_received, 1);
+        assert_eq!(stats.handshake_failures, 0);
+        assert_eq!(stats.auth_failures, 0);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_replayed_handshakes_keep_the_session() {
+        use crate::crypto::{Keypair, NoiseInitiator, NoiseResponder};
+
+        let network = MemoryNetwork::new();
+        let server = Keypair::generate();
+        let client = NoiseInitiator {
+            keypair: Keypair::generate(),
+            responder: server.public(),
+        };
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut decoded = serve(Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                noise: Some(NoiseResponder {
+                    keypair: server,
+                    authorized: vec![client.keypair.public()],
+                }),
+                ..ReceiverConfig::default()
+            },
+        ));
+        let sender_transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+        let sender_addr = sender_transport.local_addr().unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(sender_transport),
+            receiver_addr,
+            SenderConfig {
+                timeout: Duration::from_millis(200),
+                noise: Some(client.clone()),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        sender.send_object(&data).await.unwrap();
+        // A first handshake message the attacker cannot finish, such as one
+        // recorded earlier, gets a response but leaves the session alone.
+        let (replayed, _) = client.initiate(None).unwrap();
+        network.deliver(
+            Packet::HandshakeInit(replayed).serialize(),
+            sender_addr,
+            receiver_addr,
+        );
+        data.reverse();
+        sender.send_object(&data).await.unwrap();
+        let (objects, stats) = collect(&mut decoded, 2).await;
+        assert_eq!(objects[1], data);
+        assert_eq!(stats.handshakes_received, 2);
+        assert_eq!(stats.auth_failures, 0);
+    }
+
+    /// A transport that keeps a copy of everything sent through it, for an
+    /// attacker to replay.
+    #[cfg(feature = "crypto")]
+    struct Recorder {
+        inner: crate::transport::MemoryTransport,
+        sent: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+    }
+
+    #[cfg(feature = "crypto")]
+    impl DatagramTransport for Recorder {
+        fn kind(&self) -> TransportKind {
+            self.inner.kind()
+        }
+
+        fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+            self.inner.local_addr()
+        }
+
+        fn send_to<'a>(
+            &'a self,
+            buf: &'a [u8],
+            target: std::net::SocketAddr,
+        ) -> futures_util::future::BoxFuture<'a, std::io::Result<usize>> {
+            self.sent.lock().unwrap().push(buf.to_vec());
+            self.inner.send_to(buf, target)
+        }
+
+        fn recv_from<'a>(
+            &'a self,
+            buf: &'a mut [u8],
+        ) -> futures_util::future::BoxFuture<'a, std::io::Result<(usize, std::net::SocketAddr)>>
+        {
+            self.inner.recv_from(buf)
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_replayed_psk_sessions_keep_the_session() {
+        use crate::crypto::Psk;
+
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut decoded = serve(Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk: Some(Psk::new(b"correct horse")),
+                ..ReceiverConfig::default()
+            },
+        ));
+        let sender_addr = "10.0.0.2:40000".parse().unwrap();
+        let sender = |transport: Box<dyn DatagramTransport>| {
+            Sender::with_transport(
+                transport,
+                receiver_addr,
+                SenderConfig {
+                    timeout: Duration::from_millis(200),
+                    psk: Some(Psk::new(b"correct horse")),
+                    ..SenderConfig::default()
+                },
+            )
+            .unwrap()
+        };
+        let mut objects = vec![vec![0u8; DATA_SIZE as usize]; 3];
+        for object in &mut objects {
+            thread_rng().fill(&mut object[..]);
+        }
+
+        // An old session, recorded in full, that a new one from the same
+        // address then takes over from.
+        let recorded = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut old = sender(Box::new(Recorder {
+            inner: network.bind(sender_addr).unwrap(),
+            sent: recorded.clone(),
+        }));
+        old.send_object(&objects[0]).await.unwrap();
+        drop(old);
+        let mut sender = sender(Box::new(network.bind(sender_addr).unwrap()));
+        sender.send_object(&objects[1]).await.unwrap();
+
+        // Replayed, the old session's datagrams still authenticate, but must
+        // neither take over nor deliver its object again.
+        let recorded = recorded.lock().unwrap().clone();
+        for datagram in &recorded {
+            network.deliver(datagram.clone(), sender_addr, receiver_addr);
+        }
+        sender.send_object(&objects[2]).await.unwrap();
+        let (decoded, stats) = collect(&mut decoded, 3).await;
+        assert_eq!(decoded, objects);
+        assert_eq!(stats.replayed_packets, recorded.len() as u64);
+        assert_eq!(stats.objects_decoded, 3);
+        assert_eq!(stats.auth_failures, 0);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_psk_identities() {
+        use crate::crypto::Psk;
+
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:55555".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        let mut receiver = Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                psk_identities: vec![
+                    ("ops".to_string(), Psk::new(b"ops secret")),
+                    ("guests".to_string(), Psk::new(b"guest secret")),
+                ],
+                acl: "deny psk guests".parse().unwrap(),
+                ..ReceiverConfig::default()
+            },
+        );
+        let receive = tokio::spawn(async move {
+            let object = receiver.recv_object().await.unwrap();
+            (object, receiver.stats())
+        });
+        let sender = |secret: &[u8]| {
+            let transport = network.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+            Sender::with_transport(
+                Box::new(transport),
+                receiver_addr,
+                SenderConfig {
+                    handshake_timeout: Duration::from_millis(50),
+                    psk: Some(Psk::new(secret)),
+                    ..SenderConfig::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // A guest's sealed hello authenticates, but under a refused identity.
+        let mut guest = sender(b"guest secret");
+        assert!(matches!(guest.handshake().await, Err(NyxError::Timeout)));
+        let mut ops = sender(b"ops secret");
+        ops.handshake().await.unwrap();
+        let mut data = vec![0u8; DATA_SIZE as usize];
+        thread_rng().fill(&mut data[..]);
+        ops.send_object(&data).await.unwrap();
+        let (object, stats) = receive.await.unwrap();
+        assert_eq!(object.data, data);
+        assert!(stats.denied_packets > 0);
+        assert_eq!(stats.auth_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_multi_block_objects() {
+        let network = MemoryNetwork::new();
+        let receiver_transport = network.bind("10.0.0.1:9000".parse().unwrap()).unwrap();
+        let receiver_addr = receiver_transport.local_addr().unwrap();
+        // The whole object arrives in one burst, faster than any peer should
+        // usually send.
+        let mut decoded = serve(Receiver::with_transport(
+            Box::new(receiver_transport),
+            ReceiverConfig {
+                limits: Limits {
+                    max_packets_per_second: u32::MAX,
+                    ..Limits::default()
+                },
+                ..ReceiverConfig::default()
+            },
+        ));
+        let transport = network.bind("10.0.0.2:9000".parse().unwrap()).unwrap();
+        let mut sender = Sender::with_transport(
+            Box::new(transport),
+            receiver_addr,
+            SenderConfig {
+                timeout: Duration::from_secs(30),
+                ..SenderConfig::default()
+            },
+        )
+        .unwrap();
+
+        // More symbols than fit in one source block, even of the largest size.
+        let mut data = vec![0u8; 30_000_000];
+        thread_rng().fill(&mut data[..]);
+        let oti = ObjectTransmissionInformation::with_defaults(data.len() as u64, MIN_SYMBOL_SIZE);
+        assert!(oti.source_blocks() > 1);
+
+        sender.handshake().await.unwrap();
+        sender.send_object(&data).await.unwrap();
+        let (objects, _) = collect(&mut decoded, 1).await;
+        assert!(objects[0] == data);
+    }
 }
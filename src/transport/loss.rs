@@ -0,0 +1,288 @@
+// transport/loss.rs
+//
+// Which datagrams a simulated link loses. Real links, wireless and satellite
+// ones especially, lose datagrams in bursts rather than independently, and
+// an erasure code that copes with 10% random loss may not cope with 10% loss
+// arriving twenty datagrams at a time. A `LossModel` describes the pattern;
+// a `LossProcess` follows it one datagram at a time.
+//
+// Models are written as text for configuration and bench labels:
+//
+//     0.1                          10% independent loss
+//     burst:0.1,5                  10% loss in bursts averaging 5 datagrams
+//     ge:0.02,0.2,0.001,0.9        Gilbert-Elliott: p, r, good and bad loss
+//     markov:0,0.5,1;0.9,0.1,0;0.2,0.7,0.1;0,0.5,0.5
+//                                  loss per state, then one row of
+//                                  transition probabilities per state
+use rand::Rng;
+use std::{fmt, str::FromStr};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LossModel {
+    /// Each datagram is lost independently with this probability.
+    Bernoulli(f64),
+    /// A good and a bad state, with their own loss probabilities. Before
+    /// each datagram the link goes from good to bad with probability `p`
+    /// and from bad to good with probability `r`.
+    GilbertElliott {
+        p: f64,
+        r: f64,
+        good_loss: f64,
+        bad_loss: f64,
+    },
+    /// Any number of states, each with its loss probability and a row of
+    /// probabilities of moving to each state before the next datagram. The
+    /// link starts in the first state.
+    Markov {
+        loss: Vec<f64>,
+        transitions: Vec<Vec<f64>>,
+    },
+}
+
+impl Default for LossModel {
+    fn default() -> Self {
+        LossModel::Bernoulli(0.0)
+    }
+}
+
+impl LossModel {
+    /// A Gilbert model losing `rate` of all datagrams, in bursts of
+    /// `mean_burst` datagrams on average: everything is lost in the bad
+    /// state and nothing in the good one.
+    pub fn bursty(rate: f64, mean_burst: f64) -> Self {
+        let rate = rate.clamp(0.0, 1.0);
+        let r = 1.0 / mean_burst.max(1.0);
+        let p = if rate < 1.0 {
+            (rate * r / (1.0 - rate)).min(1.0)
+        } else {
+            1.0
+        };
+        LossModel::GilbertElliott {
+            p,
+            r,
+            good_loss: 0.0,
+            bad_loss: 1.0,
+        }
+    }
+
+    /// The share of datagrams lost in the long run.
+    pub fn loss_rate(&self) -> f64 {
+        match self {
+            LossModel::Bernoulli(loss) => *loss,
+            LossModel::GilbertElliott {
+                p,
+                r,
+                good_loss,
+                bad_loss,
+            } => {
+                if p + r == 0.0 {
+                    return *good_loss;
+                }
+                let bad = p / (p + r);
+                (1.0 - bad) * good_loss + bad * bad_loss
+            }
+            LossModel::Markov { loss, transitions } => {
+                // Power iteration from the starting state converges for
+                // any chain a link would sensibly be modelled with.
+                let mut share = vec![0.0; loss.len()];
+                if let Some(first) = share.first_mut() {
+                    *first = 1.0;
+                }
+                for _ in 0..10_000 {
+                    let mut next = vec![0.0; share.len()];
+                    for (from, row) in transitions.iter().enumerate() {
+                        for (to, probability) in row.iter().enumerate() {
+                            next[to] += share[from] * probability;
+                        }
+                    }
+                    share = next;
+                }
+                share
+                    .iter()
+                    .zip(loss)
+                    .map(|(share, loss)| share * loss)
+                    .sum()
+            }
+        }
+    }
+}
+
+impl FromStr for LossModel {
+    type Err = String;
+
+    /// Parses a model in one of the forms described at the top of this
+    /// module.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, args) = s.trim().split_once(':').unwrap_or(("bernoulli", s.trim()));
+        let numbers = |args: &str| -> Result<Vec<f64>, String> {
+            args.split(',')
+                .map(|n| {
+                    n.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|n| n.is_finite() && *n >= 0.0)
+                        .ok_or_else(|| format!("not a non-negative number: {}", n))
+                })
+                .collect()
+        };
+        let probabilities = |args: &str| -> Result<Vec<f64>, String> {
+            let numbers = numbers(args)?;
+            match numbers.iter().find(|&&n| n > 1.0) {
+                Some(n) => Err(format!("not a probability: {}", n)),
+                None => Ok(numbers),
+            }
+        };
+        let model = match kind {
+            "bernoulli" => match probabilities(args)?[..] {
+                [loss] => LossModel::Bernoulli(loss),
+                _ => return Err(format!("expected one loss probability: {}", s)),
+            },
+            "burst" => match numbers(args)?[..] {
+                [rate, mean_burst] if rate <= 1.0 && mean_burst >= 1.0 => {
+                    LossModel::bursty(rate, mean_burst)
+                }
+                _ => {
+                    return Err(format!(
+                        "expected a loss rate and a mean burst length: {}",
+                        s
+                    ))
+                }
+            },
+            "ge" | "gilbert-elliott" => match probabilities(args)?[..] {
+                [p, r] => LossModel::GilbertElliott {
+                    p,
+                    r,
+                    good_loss: 0.0,
+                    bad_loss: 1.0,
+                },
+                [p, r, good_loss, bad_loss] => LossModel::GilbertElliott {
+                    p,
+                    r,
+                    good_loss,
+                    bad_loss,
+                },
+                _ => {
+                    return Err(format!(
+                        "expected p, r and optionally the loss in each state: {}",
+                        s
+                    ))
+                }
+            },
+            "markov" => {
+                let mut groups = args.split(';');
+                let loss = probabilities(groups.next().unwrap_or_default())?;
+                let transitions = groups.map(probabilities).collect::<Result<Vec<_>, _>>()?;
+                if transitions.len() != loss.len()
+                    || transitions.iter().any(|row| row.len() != loss.len())
+                {
+                    return Err(format!(
+                        "expected one row of {} transitions per state: {}",
+                        loss.len(),
+                        s
+                    ));
+                }
+                if let Some(row) = transitions
+                    .iter()
+                    .find(|row| (row.iter().sum::<f64>() - 1.0).abs() > 1e-6)
+                {
+                    return Err(format!("transitions do not add up to 1: {:?}", row));
+                }
+                LossModel::Markov { loss, transitions }
+            }
+            _ => return Err(format!("not a loss model: {}", s)),
+        };
+        Ok(model)
+    }
+}
+
+impl fmt::Display for LossModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |numbers: &[f64]| {
+            numbers
+                .iter()
+                .map(f64::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        match self {
+            LossModel::Bernoulli(loss) => write!(f, "{}", loss),
+            LossModel::GilbertElliott {
+                p,
+                r,
+                good_loss,
+                bad_loss,
+            } => write!(f, "ge:{}", list(&[*p, *r, *good_loss, *bad_loss])),
+            LossModel::Markov { loss, transitions } => {
+                write!(f, "markov:{}", list(loss))?;
+                transitions
+                    .iter()
+                    .try_for_each(|row| write!(f, ";{}", list(row)))
+            }
+        }
+    }
+}
+
+/// `p` as a usable probability: clamped to [0, 1], or 0 if it is not a
+/// number at all.
+pub(super) fn probability(p: f64) -> f64 {
+    if p.is_finite() {
+        p.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// A [`LossModel`] being followed, one datagram at a time.
+#[derive(Clone, Debug)]
+pub struct LossProcess {
+    model: LossModel,
+    state: usize,
+}
+
+impl LossProcess {
+    pub fn new(model: LossModel) -> Self {
+        LossProcess { model, state: 0 }
+    }
+
+    pub fn model(&self) -> &LossModel {
+        &self.model
+    }
+
+    /// Moves the link on by one datagram and says whether it is lost.
+    pub fn lose(&mut self, rng: &mut impl Rng) -> bool {
+        let loss = match &self.model {
+            LossModel::Bernoulli(loss) => *loss,
+            LossModel::GilbertElliott {
+                p,
+                r,
+                good_loss,
+                bad_loss,
+            } => {
+                let leave = if self.state == 0 { p } else { r };
+                if rng.gen_bool(probability(*leave)) {
+                    self.state = 1 - self.state;
+                }
+                if self.state == 0 {
+                    *good_loss
+                } else {
+                    *bad_loss
+                }
+            }
+            LossModel::Markov { loss, transitions } => {
+                if let Some(row) = transitions.get(self.state) {
+                    let mut draw: f64 = rng.gen();
+                    // Rounding may leave a sliver past the last state; it
+                    // stays put.
+                    if let Some(next) = row.iter().position(|&probability| {
+                        draw -= probability;
+                        draw < 0.0
+                    }) {
+                        self.state = next;
+                    }
+                }
+                loss.get(self.state).copied().unwrap_or_default()
+            }
+        };
+        rng.gen_bool(probability(loss))
+    }
+}
@@ -0,0 +1,119 @@
+// transport/memory.rs
+use super::{DatagramTransport, TransportKind};
+use futures_util::future::{BoxFuture, FutureExt};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+
+type Datagram = (Vec<u8>, SocketAddr);
+
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+#[derive(Default)]
+struct Endpoints {
+    inboxes: HashMap<SocketAddr, mpsc::UnboundedSender<Datagram>>,
+    next_port: u16,
+}
+
+/// A set of in-process endpoints that can reach each other by address, for
+/// tests that should not touch real sockets. Cloning yields a handle to the
+/// same network.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    endpoints: Arc<Mutex<Endpoints>>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds an endpoint at `addr`; port 0 picks a free port.
+    pub fn bind(&self, mut addr: SocketAddr) -> io::Result<MemoryTransport> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if addr.port() == 0 {
+            loop {
+                let port = FIRST_EPHEMERAL_PORT.wrapping_add(endpoints.next_port);
+                endpoints.next_port = endpoints.next_port.wrapping_add(1);
+                addr.set_port(port);
+                if port != 0 && !endpoints.inboxes.contains_key(&addr) {
+                    break;
+                }
+            }
+        } else if endpoints.inboxes.contains_key(&addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already bound", addr),
+            ));
+        }
+        let (inbox, rx) = mpsc::unbounded_channel();
+        endpoints.inboxes.insert(addr, inbox);
+        Ok(MemoryTransport {
+            addr,
+            network: self.clone(),
+            rx: tokio::sync::Mutex::new(rx),
+        })
+    }
+
+    /// Hands `datagram` to whoever is bound at `to`. Like UDP, sending to an
+    /// address nobody listens on silently drops the datagram.
+    pub(crate) fn deliver(&self, datagram: Vec<u8>, from: SocketAddr, to: SocketAddr) {
+        let endpoints = self.endpoints.lock().unwrap();
+        if let Some(inbox) = endpoints.inboxes.get(&to) {
+            let _ = inbox.send((datagram, from));
+        }
+    }
+}
+
+/// One endpoint on a [`MemoryNetwork`].
+pub struct MemoryTransport {
+    addr: SocketAddr,
+    network: MemoryNetwork,
+    rx: tokio::sync::Mutex<mpsc::UnboundedReceiver<Datagram>>,
+}
+
+impl DatagramTransport for MemoryTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Memory
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> BoxFuture<'a, io::Result<usize>> {
+        self.network.deliver(buf.to_vec(), self.addr, target);
+        futures_util::future::ready(Ok(buf.len())).boxed()
+    }
+
+    fn recv_from<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, io::Result<(usize, SocketAddr)>> {
+        async move {
+            let (datagram, from) = self.rx.lock().await.recv().await.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotConnected, "memory network closed")
+            })?;
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok((datagram.len(), from))
+        }
+        .boxed()
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        if let Ok(mut endpoints) = self.network.endpoints.lock() {
+            endpoints.inboxes.remove(&self.addr);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// transport/mod.rs
+//
+// Everything nyx-ψ sends goes through a `DatagramTransport`, so the protocol
+// runs unchanged over UDP, UDP-Lite or an in-process channel.
+mod loss;
+mod memory;
+mod sim;
+mod trace;
+mod udp;
+mod udp_lite;
+
+pub use loss::{LossModel, LossProcess};
+pub use memory::{MemoryNetwork, MemoryTransport};
+pub use sim::{LinkConfig, LinkStats, SimulatedTransport};
+pub use trace::{Replay, Trace, TracePlayer, TraceRecord};
+pub use udp::UdpTransport;
+pub use udp_lite::{UdpLiteTransport, CHECKSUM_COVERAGE};
+
+use futures_util::future::BoxFuture;
+use socket2::SockRef;
+use std::{fmt, io, mem::MaybeUninit, net::SocketAddr, str::FromStr};
+use tokio::{io::Interest, net::UdpSocket};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    Udp,
+    UdpLite,
+    Memory,
+}
+
+impl fmt::Display for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransportKind::Udp => "udp",
+            TransportKind::UdpLite => "udplite",
+            TransportKind::Memory => "memory",
+        })
+    }
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "udp" => Ok(TransportKind::Udp),
+            "udplite" | "udp-lite" => Ok(TransportKind::UdpLite),
+            "memory" => Ok(TransportKind::Memory),
+            _ => Err(format!("unknown transport: {}", s)),
+        }
+    }
+}
+
+/// An unreliable, unordered datagram service.
+pub trait DatagramTransport: Send + Sync {
+    fn kind(&self) -> TransportKind;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: SocketAddr)
+        -> BoxFuture<'a, io::Result<usize>>;
+
+    /// Receives one datagram into `buf`, returning its full length. A length
+    /// larger than `buf.len()` means the datagram was truncated.
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8])
+        -> BoxFuture<'a, io::Result<(usize, SocketAddr)>>;
+
+    /// Sets the Don't Fragment bit on outgoing datagrams, for path MTU
+    /// discovery. Transports without fragmentation need not do anything.
+    fn set_dont_fragment(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Binds a socket-backed transport of the given kind. In-memory transports
+/// are bound through a [`MemoryNetwork`] instead.
+///
+/// Must be called from within a tokio runtime.
+pub fn bind(kind: TransportKind, addr: SocketAddr) -> io::Result<Box<dyn DatagramTransport>> {
+    match kind {
+        TransportKind::Udp => Ok(Box::new(UdpTransport::bind(addr)?)),
+        TransportKind::UdpLite => Ok(Box::new(UdpLiteTransport::bind(addr)?)),
+        TransportKind::Memory => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory transports are bound through a MemoryNetwork",
+        )),
+    }
+}
+
+// With MSG_TRUNC Linux reports the real datagram length even when it did not
+// fit. Elsewhere a truncated datagram comes back cut short, and fails packet
+// validation instead.
+#[cfg(target_os = "linux")]
+const RECV_FLAGS: libc::c_int = libc::MSG_TRUNC;
+#[cfg(not(target_os = "linux"))]
+const RECV_FLAGS: libc::c_int = 0;
+
+/// `recv_from` for the socket transports, reporting truncation.
+async fn recv_datagram(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    socket
+        .async_io(Interest::READABLE, || {
+            // SAFETY: recv only ever writes initialised bytes into the buffer.
+            let uninit = unsafe { &mut *(&mut *buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+            let (len, addr) = SockRef::from(socket).recv_from_with_flags(uninit, RECV_FLAGS)?;
+            let addr = addr.as_socket().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "non-IP source address")
+            })?;
+            Ok((len, addr))
+        })
+        .await
+}
@@ -0,0 +1,372 @@
+// transport/sim.rs
+//
+// A lossy link in front of another transport, for testing the protocol
+// under bad network conditions without leaving the process. Whatever is sent
+// through a `SimulatedTransport` may be dropped, delayed, reordered,
+// duplicated, queued behind a bandwidth cap or have a bit flipped before the
+// inner transport sends it on; what it receives passes through untouched, so
+// a link impaired both ways wraps both of its ends.
+//
+// All random choices come from one RNG seeded from the config, so a run with
+// the same seed and the same traffic makes the same choices. Loss and delay
+// can instead be replayed from a trace of a real link.
+use super::{
+    loss::probability, DatagramTransport, LossModel, LossProcess, Replay, Trace, TracePlayer,
+    TransportKind,
+};
+use futures_util::future::{BoxFuture, FutureExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, Instant},
+};
+
+/// Impairments a [`SimulatedTransport`] applies to what it sends.
+/// Probabilities are per datagram, and taken as 0 if they are not numbers.
+/// The default is a perfect link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkConfig {
+    /// Which datagrams are dropped.
+    pub loss: LossModel,
+    /// One-way delay every datagram gets.
+    pub delay: Duration,
+    /// Loss and delay recorded on a real link, replayed in place of `loss`
+    /// and `delay`. The other impairments still apply on top.
+    pub trace: Option<Trace>,
+    pub replay: Replay,
+    /// Up to this much more delay, drawn uniformly per datagram. Datagrams
+    /// with different jitter can overtake each other.
+    pub jitter: Duration,
+    /// Chance that a datagram is held back a further `reorder_delay`, so
+    /// that the ones after it arrive first.
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Chance that a datagram is delivered twice.
+    pub duplicate: f64,
+    /// Chance that one bit of a datagram is flipped.
+    pub corrupt: f64,
+    /// Link rate in bits per second. Datagrams queue behind each other and
+    /// are dropped if they would wait longer than `max_queue_delay`.
+    pub bandwidth: Option<u64>,
+    pub max_queue_delay: Duration,
+    pub seed: u64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        LinkConfig {
+            loss: LossModel::default(),
+            delay: Duration::ZERO,
+            trace: None,
+            replay: Replay::default(),
+            jitter: Duration::ZERO,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            duplicate: 0.0,
+            corrupt: 0.0,
+            bandwidth: None,
+            max_queue_delay: Duration::from_millis(100),
+            seed: 0,
+        }
+    }
+}
+
+impl FromStr for LinkConfig {
+    type Err = String;
+
+    /// Parses space-separated `name=value` settings, after `tc netem`:
+    /// `loss` takes a [`LossModel`], `delay`, `jitter`, `reorder_delay` and
+    /// `queue` (the longest queue delay) milliseconds, `reorder`,
+    /// `duplicate` and `corrupt` probabilities, `rate` bits per second with
+    /// an optional `k`, `M` or `G` suffix, and `seed` a number. Settings
+    /// not given keep their defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = LinkConfig::default();
+        for setting in s.split_whitespace() {
+            let error = || format!("not a link setting: {}", setting);
+            let (name, value) = setting.split_once('=').ok_or_else(error)?;
+            let millis = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|ms| ms.is_finite() && *ms >= 0.0)
+                    .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+                    .ok_or_else(error)
+            };
+            let probability = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or_else(error)
+            };
+            match name {
+                "loss" => config.loss = value.parse()?,
+                "delay" => config.delay = millis()?,
+                "jitter" => config.jitter = millis()?,
+                "reorder" => config.reorder = probability()?,
+                "reorder_delay" => config.reorder_delay = millis()?,
+                "duplicate" => config.duplicate = probability()?,
+                "corrupt" => config.corrupt = probability()?,
+                "rate" => {
+                    let (digits, scale) = match value.char_indices().last() {
+                        Some((i, 'k')) => (&value[..i], 1e3),
+                        Some((i, 'M')) => (&value[..i], 1e6),
+                        Some((i, 'G')) => (&value[..i], 1e9),
+                        _ => (value, 1.0),
+                    };
+                    let rate = digits
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate >= 1.0)
+                        .ok_or_else(error)?;
+                    config.bandwidth = Some((rate * scale) as u64);
+                }
+                "queue" => config.max_queue_delay = millis()?,
+                "seed" => config.seed = value.parse().map_err(|_| error())?,
+                _ => return Err(error()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl LinkConfig {
+    /// The config with its probabilities made usable, so that drawing
+    /// against them cannot panic.
+    fn checked(mut self) -> Self {
+        self.reorder = probability(self.reorder);
+        self.duplicate = probability(self.duplicate);
+        self.corrupt = probability(self.corrupt);
+        self
+    }
+}
+
+/// What a [`SimulatedTransport`] has done to the datagrams sent through it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    /// Bytes of the datagrams sent, lost ones included.
+    pub bytes: u64,
+    pub lost: u64,
+    /// Dropped because the queue behind the bandwidth cap was full.
+    pub overflowed: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    pub corrupted: u64,
+}
+
+struct Link {
+    config: LinkConfig,
+    loss: LossProcess,
+    trace: Option<TracePlayer>,
+    /// When the link came up, or its trace last changed.
+    started: Instant,
+    rng: StdRng,
+    stats: LinkStats,
+    /// When the link finishes sending what is already queued on it.
+    busy_until: Instant,
+}
+
+/// A datagram on its way, ordered by when it arrives and then by when it
+/// was sent.
+type InFlight = (Reverse<(Instant, u64)>, Vec<u8>, SocketAddr);
+
+/// A transport whose outgoing datagrams cross a simulated [`LinkConfig`]
+/// before the inner transport sends them.
+pub struct SimulatedTransport {
+    inner: Arc<dyn DatagramTransport>,
+    link: Mutex<Link>,
+    next_seq: Mutex<u64>,
+    in_flight: mpsc::UnboundedSender<InFlight>,
+}
+
+impl SimulatedTransport {
+    /// Puts the link described by `config` in front of `inner`.
+    ///
+    /// Must be called from within a tokio runtime, which runs the task that
+    /// hands delayed datagrams to `inner`.
+    pub fn new(inner: Box<dyn DatagramTransport>, config: LinkConfig) -> Self {
+        let config = config.checked();
+        let inner: Arc<dyn DatagramTransport> = Arc::from(inner);
+        let (in_flight, rx) = mpsc::unbounded_channel();
+        tokio::spawn(deliver(inner.clone(), rx));
+        SimulatedTransport {
+            inner,
+            link: Mutex::new(Link {
+                rng: StdRng::seed_from_u64(config.seed),
+                loss: LossProcess::new(config.loss.clone()),
+                trace: trace_player(&config),
+                started: Instant::now(),
+                config,
+                stats: LinkStats::default(),
+                busy_until: Instant::now(),
+            }),
+            next_seq: Mutex::new(0),
+            in_flight,
+        }
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.link.lock().unwrap().stats
+    }
+
+    /// Changes the link's conditions from now on, keeping its RNG, and the
+    /// state of its loss model and trace replay if they stay the same.
+    pub fn set_config(&self, config: LinkConfig) {
+        let config = config.checked();
+        let mut link = self.link.lock().unwrap();
+        if link.config.loss != config.loss {
+            link.loss = LossProcess::new(config.loss.clone());
+        }
+        if (&link.config.trace, link.config.replay) != (&config.trace, config.replay) {
+            link.trace = trace_player(&config);
+            link.started = Instant::now();
+        }
+        link.config = config;
+    }
+
+    /// Decides the fate of one datagram: the copies of it that arrive, and
+    /// when.
+    fn transmit(&self, buf: &[u8]) -> Vec<(Instant, Vec<u8>)> {
+        let mut link = self.link.lock().unwrap();
+        let Link {
+            config,
+            loss,
+            trace,
+            started,
+            rng,
+            stats,
+            busy_until,
+        } = &mut *link;
+        let now = Instant::now();
+        stats.sent += 1;
+        stats.bytes += buf.len() as u64;
+        let (lost, delay) = match trace {
+            Some(trace) => {
+                let record = trace.next(now - *started);
+                (!record.delivered, record.delay)
+            }
+            None => (loss.lose(rng), config.delay),
+        };
+        if lost {
+            stats.lost += 1;
+            return Vec::new();
+        }
+        let mut departure = now;
+        if let Some(bandwidth) = config.bandwidth {
+            let start = (*busy_until).max(now);
+            if start - now > config.max_queue_delay {
+                stats.overflowed += 1;
+                return Vec::new();
+            }
+            let transmission = buf.len() as f64 * 8.0 / bandwidth.max(1) as f64;
+            departure = start + Duration::from_secs_f64(transmission);
+            *busy_until = departure;
+        }
+        let mut datagram = buf.to_vec();
+        if !datagram.is_empty() && rng.gen_bool(config.corrupt) {
+            stats.corrupted += 1;
+            let bit = rng.gen_range(0..datagram.len() * 8);
+            datagram[bit / 8] ^= 1 << (bit % 8);
+        }
+        let copies = if rng.gen_bool(config.duplicate) {
+            stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let mut arrival = departure + delay + config.jitter.mul_f64(rng.gen());
+                if rng.gen_bool(config.reorder) {
+                    stats.reordered += 1;
+                    arrival += config.reorder_delay;
+                }
+                (arrival, datagram.clone())
+            })
+            .collect()
+    }
+}
+
+impl DatagramTransport for SimulatedTransport {
+    fn kind(&self) -> TransportKind {
+        self.inner.kind()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> BoxFuture<'a, io::Result<usize>> {
+        for (arrival, datagram) in self.transmit(buf) {
+            let mut next_seq = self.next_seq.lock().unwrap();
+            let seq = *next_seq;
+            *next_seq += 1;
+            // The delivery task only stops once this transport is dropped.
+            let _ = self
+                .in_flight
+                .send((Reverse((arrival, seq)), datagram, target));
+        }
+        futures_util::future::ready(Ok(buf.len())).boxed()
+    }
+
+    fn recv_from<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, io::Result<(usize, SocketAddr)>> {
+        self.inner.recv_from(buf)
+    }
+
+    fn set_dont_fragment(&self) -> io::Result<()> {
+        self.inner.set_dont_fragment()
+    }
+}
+
+fn trace_player(config: &LinkConfig) -> Option<TracePlayer> {
+    config
+        .trace
+        .clone()
+        .map(|trace| TracePlayer::new(trace, config.replay))
+}
+
+/// Hands datagrams to `inner` as they arrive, until the transport is
+/// dropped and everything still in flight has been delivered.
+async fn deliver(inner: Arc<dyn DatagramTransport>, mut rx: mpsc::UnboundedReceiver<InFlight>) {
+    let mut queue: BinaryHeap<InFlight> = BinaryHeap::new();
+    let mut open = true;
+    while open || !queue.is_empty() {
+        let next = queue.peek().map(|(Reverse((arrival, _)), ..)| *arrival);
+        tokio::select! {
+            // Polled in order rather than at random, so that runs repeat.
+            biased;
+            received = rx.recv(), if open => match received {
+                Some(datagram) => queue.push(datagram),
+                None => open = false,
+            },
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                let now = Instant::now();
+                while let Some((Reverse((arrival, _)), ..)) = queue.peek() {
+                    if *arrival > now {
+                        break;
+                    }
+                    let (_, datagram, target) = queue.pop().expect("peeked");
+                    let _ = inner.send_to(&datagram, target).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// transport/trace.rs
+//
+// Loss and delay recorded on a real link, for a simulated link to replay so
+// that runs against the same trace are comparable across versions. A trace
+// is a list of records, each saying whether a datagram sent at a point in
+// the capture was delivered and how long it took. It is written as CSV:
+//
+//     time_ms,delivered,delay_ms
+//     0,1,41.5
+//     20,0,
+//     40,1,43
+//
+// (the header is optional, and `true`/`false` do as well as 1/0) or as a
+// JSON array of objects with the same keys:
+//
+//     [{"time_ms": 0, "delivered": true, "delay_ms": 41.5}, ...]
+use std::{fmt, fs, io, path::Path, str::FromStr, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceRecord {
+    /// When in the capture the datagram was sent.
+    pub time: Duration,
+    pub delivered: bool,
+    /// How long it took to arrive. Ignored for lost datagrams.
+    pub delay: Duration,
+}
+
+/// Records in order of time, at least one of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    records: Vec<TraceRecord>,
+}
+
+/// How a trace is stepped through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Replay {
+    /// Each datagram meets the fate of the record in effect at the time it
+    /// is sent, counted from the start of the replay.
+    #[default]
+    Time,
+    /// Each datagram meets the fate of the next record, whenever it is
+    /// sent, so that the same traffic sees the same losses however fast it
+    /// goes.
+    Packets,
+}
+
+impl Trace {
+    /// Reads a trace from a CSV or JSON file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(&path)?.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.as_ref().display(), e),
+            )
+        })
+    }
+
+    pub fn from_csv(s: &str) -> Result<Self, String> {
+        let mut records = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || (number == 0 && line.starts_with("time"))
+            {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let record = match fields[..] {
+                [time, delivered] => parse_record(time, delivered, ""),
+                [time, delivered, delay] => parse_record(time, delivered, delay),
+                _ => Err(format!("expected time, delivered and delay: {}", line)),
+            };
+            records.push(record.map_err(|e| format!("line {}: {}", number + 1, e))?);
+        }
+        Trace::new(records)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(s).map_err(|e| format!("not JSON: {}", e))?;
+        let entries = value.as_array().ok_or("expected an array of records")?;
+        let mut records = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            records.push(json_record(entry).map_err(|e| format!("record {}: {}", index, e))?);
+        }
+        Trace::new(records)
+    }
+
+    /// A trace of `records`, which must be in order of time and must not be
+    /// empty.
+    pub fn new(records: Vec<TraceRecord>) -> Result<Self, String> {
+        if records.is_empty() {
+            return Err("no records".to_string());
+        }
+        if let Some(pair) = records.windows(2).find(|pair| pair[1].time < pair[0].time) {
+            return Err(format!(
+                "records out of order at {} ms",
+                millis(pair[1].time)
+            ));
+        }
+        Ok(Trace { records })
+    }
+
+    /// How long the capture ran: up to the last record, plus the typical
+    /// gap between records so that a replay that loops does not give the
+    /// last record no time at all.
+    pub fn duration(&self) -> Duration {
+        let (Some(first), Some(last)) = (self.records.first(), self.records.last()) else {
+            return Duration::ZERO;
+        };
+        let span = last.time - first.time;
+        match self.records.len() {
+            1 => Duration::from_millis(1),
+            n => span + span / (n as u32 - 1),
+        }
+    }
+
+    pub fn records(&self) -> &[TraceRecord] {
+        &self.records
+    }
+
+    /// The share of datagrams the trace loses.
+    pub fn loss_rate(&self) -> f64 {
+        let lost = self.records.iter().filter(|r| !r.delivered).count();
+        lost as f64 / self.records.len().max(1) as f64
+    }
+
+    /// The record in effect `elapsed` into a replay, looping when the trace
+    /// runs out.
+    pub fn at(&self, elapsed: Duration) -> &TraceRecord {
+        let first = self.records[0].time;
+        let duration = self.duration().as_nanos().max(1);
+        let offset = first + Duration::from_nanos((elapsed.as_nanos() % duration) as u64);
+        let index = self.records.partition_point(|r| r.time <= offset);
+        &self.records[index.saturating_sub(1)]
+    }
+
+    /// The `n`th record, looping when the trace runs out.
+    pub fn nth(&self, n: u64) -> &TraceRecord {
+        &self.records[(n % self.records.len() as u64) as usize]
+    }
+}
+
+impl FromStr for Trace {
+    type Err = String;
+
+    /// Parses a trace as JSON if it looks like JSON, and as CSV otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('[') {
+            Trace::from_json(s)
+        } else {
+            Trace::from_csv(s)
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    /// Writes the trace as CSV.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "time_ms,delivered,delay_ms")?;
+        for record in &self.records {
+            write!(f, "{},{},", millis(record.time), record.delivered as u8)?;
+            if record.delivered {
+                write!(f, "{}", millis(record.delay))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1e6
+}
+
+/// A time given in milliseconds, if it is one.
+fn from_millis(ms: f64) -> Option<Duration> {
+    (ms.is_finite() && ms >= 0.0).then(|| Duration::from_nanos((ms * 1e6).round() as u64))
+}
+
+fn parse_record(time: &str, delivered: &str, delay: &str) -> Result<TraceRecord, String> {
+    let parse_millis = |s: &str| {
+        s.parse::<f64>()
+            .ok()
+            .and_then(from_millis)
+            .ok_or_else(|| format!("not a time in milliseconds: {}", s))
+    };
+    let delivered = match delivered {
+        "1" | "true" => true,
+        "0" | "false" => false,
+        _ => return Err(format!("not 1, 0, true or false: {}", delivered)),
+    };
+    Ok(TraceRecord {
+        time: parse_millis(time)?,
+        delivered,
+        delay: match delay {
+            "" => Duration::ZERO,
+            delay => parse_millis(delay)?,
+        },
+    })
+}
+
+fn json_record(entry: &serde_json::Value) -> Result<TraceRecord, String> {
+    let millis = |name: &str| match entry.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .and_then(from_millis)
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a time in milliseconds: {}", name, value)),
+    };
+    let delivered = match entry.get("delivered") {
+        Some(value) => match (value.as_bool(), value.as_u64()) {
+            (Some(delivered), _) => delivered,
+            (_, Some(1)) => true,
+            (_, Some(0)) => false,
+            _ => return Err(format!("delivered is not true, false, 1 or 0: {}", value)),
+        },
+        None => return Err("expected delivered".to_string()),
+    };
+    Ok(TraceRecord {
+        time: millis("time_ms")?.ok_or("expected time_ms")?,
+        delivered,
+        delay: millis("delay_ms")?.unwrap_or_default(),
+    })
+}
+
+/// A [`Trace`] being replayed.
+#[derive(Clone, Debug)]
+pub struct TracePlayer {
+    trace: Trace,
+    replay: Replay,
+    sent: u64,
+}
+
+impl TracePlayer {
+    pub fn new(trace: Trace, replay: Replay) -> Self {
+        TracePlayer {
+            trace,
+            replay,
+            sent: 0,
+        }
+    }
+
+    /// The record for a datagram sent `elapsed` into the replay.
+    pub fn next(&mut self, elapsed: Duration) -> TraceRecord {
+        self.sent += 1;
+        match self.replay {
+            Replay::Time => *self.trace.at(elapsed),
+            Replay::Packets => *self.trace.nth(self.sent - 1),
+        }
+    }
+}
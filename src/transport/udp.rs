@@ -0,0 +1,51 @@
+// transport/udp.rs
+use super::{recv_datagram, DatagramTransport, TransportKind};
+use crate::pmtu::set_dont_fragment;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::{io, net::SocketAddr};
+use tokio::net::UdpSocket;
+
+/// Plain UDP, for hosts and middleboxes that drop UDP-Lite.
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    /// Must be called from within a tokio runtime.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = std::net::UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport {
+            socket: UdpSocket::from_std(socket)?,
+        })
+    }
+}
+
+impl DatagramTransport for UdpTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Udp
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> BoxFuture<'a, io::Result<usize>> {
+        self.socket.send_to(buf, target).boxed()
+    }
+
+    fn recv_from<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, io::Result<(usize, SocketAddr)>> {
+        recv_datagram(&self.socket, buf).boxed()
+    }
+
+    fn set_dont_fragment(&self) -> io::Result<()> {
+        set_dont_fragment(&self.socket)
+    }
+}
@@ -0,0 +1,66 @@
+// transport/udp_lite.rs
+use super::{recv_datagram, DatagramTransport, TransportKind};
+use crate::{packet::COVERED_SIZE, pmtu::set_dont_fragment};
+use futures_util::future::{BoxFuture, FutureExt};
+use std::{io, net::SocketAddr};
+use tokio::net::UdpSocket;
+use udplite::UdpLiteSocket;
+
+const UDP_LITE_HEADER_SIZE: u16 = 8;
+
+/// Checksum coverage used by both ends, counted from the start of the
+/// UDP-Lite header: that header plus our own covered prefix. Damage past it
+/// is still delivered, and caught by the per-symbol checksum instead.
+pub const CHECKSUM_COVERAGE: u16 = UDP_LITE_HEADER_SIZE + COVERED_SIZE as u16;
+
+/// UDP-Lite (IP protocol 136), the original nyx-ψ transport.
+pub struct UdpLiteTransport {
+    socket: UdpSocket,
+}
+
+impl UdpLiteTransport {
+    /// Binds a non-blocking UDP-Lite socket and registers it with tokio.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpLiteSocket::bind_nonblocking(addr)?;
+        socket.set_send_checksum_coverage(Some(CHECKSUM_COVERAGE))?;
+        socket.set_recv_checksum_coverage_filter(Some(CHECKSUM_COVERAGE))?;
+        // UDP-Lite sockets are plain datagram sockets as far as send/recv go.
+        // The clone shares the socket, options and non-blocking mode
+        // included; the original descriptor is closed when `socket` drops.
+        let std_socket = std::net::UdpSocket::try_clone(&socket)?;
+        Ok(UdpLiteTransport {
+            socket: UdpSocket::from_std(std_socket)?,
+        })
+    }
+}
+
+impl DatagramTransport for UdpLiteTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::UdpLite
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> BoxFuture<'a, io::Result<usize>> {
+        self.socket.send_to(buf, target).boxed()
+    }
+
+    fn recv_from<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, io::Result<(usize, SocketAddr)>> {
+        recv_datagram(&self.socket, buf).boxed()
+    }
+
+    fn set_dont_fragment(&self) -> io::Result<()> {
+        set_dont_fragment(&self.socket)
+    }
+}
@@ -0,0 +1,127 @@
+// validation.rs
+//
+// Stateless address validation, after QUIC's Retry (RFC 9000 section 8.1).
+// A receiver answers a hello from an address it has not validated with a
+// cookie bound to that address, and holds no state for the address until a
+// hello comes back echoing the cookie. Until then the only reply the address
+// gets is the cookie itself, no larger than the hello that asked for it.
+use crate::packet::COOKIE_SIZE;
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
+use tokio::time::Instant;
+
+/// Cookies are minted per epoch of this length and accepted during the
+/// epoch they were minted in and the one after.
+pub const COOKIE_LIFETIME: Duration = Duration::from_secs(30);
+/// Default bound on how many times larger than a datagram from an
+/// unvalidated address the reply to it may be, as in QUIC.
+pub const AMPLIFICATION_LIMIT: usize = 3;
+
+/// Mints and checks address validation cookies, and remembers the addresses
+/// that have echoed one until they are forgotten.
+pub struct AddressValidator {
+    secret: [u8; 32],
+    started: Instant,
+    /// Kept by the receiver to the addresses it holds sessions for.
+    validated: HashSet<SocketAddr>,
+}
+
+impl AddressValidator {
+    pub fn new() -> Self {
+        AddressValidator {
+            secret: rand::random(),
+            started: Instant::now(),
+            validated: HashSet::new(),
+        }
+    }
+
+    pub fn is_validated(&self, peer: SocketAddr) -> bool {
+        self.validated.contains(&peer)
+    }
+
+    /// A cookie only `peer` can echo, having received it at its address.
+    pub fn cookie(&self, peer: SocketAddr) -> [u8; COOKIE_SIZE] {
+        self.cookie_for_epoch(peer, None, self.epoch())
+    }
+
+    /// A cookie for `peer` to echo in session `session_id`, which only a
+    /// party holding that session's keys can read if it is sent sealed.
+    pub fn session_cookie(&self, peer: SocketAddr, session_id: u64) -> [u8; COOKIE_SIZE] {
+        self.cookie_for_epoch(peer, Some(session_id), self.epoch())
+    }
+
+    /// Whether `cookie` is one minted recently for `peer` in session
+    /// `session_id`.
+    pub fn check_session(
+        &self,
+        peer: SocketAddr,
+        session_id: u64,
+        cookie: &[u8; COOKIE_SIZE],
+    ) -> bool {
+        self.is_recent(peer, Some(session_id), cookie)
+    }
+
+    /// Whether `cookie` is one minted for `peer` recently. It takes a call
+    /// to [`validate`](Self::validate) for the address to count as validated.
+    pub fn check(&self, peer: SocketAddr, cookie: &[u8; COOKIE_SIZE]) -> bool {
+        self.is_recent(peer, None, cookie)
+    }
+
+    /// Remembers `peer` as validated, once it has echoed a cookie.
+    pub fn validate(&mut self, peer: SocketAddr) {
+        self.validated.insert(peer);
+    }
+
+    /// Forgets that `peer` was validated, so that it has to echo a cookie
+    /// again.
+    pub fn forget(&mut self, peer: SocketAddr) {
+        self.validated.remove(&peer);
+    }
+
+    fn is_recent(
+        &self,
+        peer: SocketAddr,
+        session_id: Option<u64>,
+        cookie: &[u8; COOKIE_SIZE],
+    ) -> bool {
+        let epoch = self.epoch();
+        [Some(epoch), epoch.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .any(|epoch| constant_time_eq(&self.cookie_for_epoch(peer, session_id, epoch), cookie))
+    }
+
+    fn epoch(&self) -> u64 {
+        self.started.elapsed().as_secs() / COOKIE_LIFETIME.as_secs()
+    }
+
+    fn cookie_for_epoch(
+        &self,
+        peer: SocketAddr,
+        session_id: Option<u64>,
+        epoch: u64,
+    ) -> [u8; COOKIE_SIZE] {
+        let mut hasher = blake3::Hasher::new_keyed(&self.secret);
+        match peer {
+            SocketAddr::V4(addr) => hasher.update(&addr.ip().octets()),
+            SocketAddr::V6(addr) => hasher.update(&addr.ip().octets()),
+        };
+        hasher.update(&peer.port().to_be_bytes());
+        hasher.update(&epoch.to_be_bytes());
+        if let Some(session_id) = session_id {
+            hasher.update(&session_id.to_be_bytes());
+        }
+        let mut cookie = [0u8; COOKIE_SIZE];
+        cookie.copy_from_slice(&hasher.finalize().as_bytes()[..COOKIE_SIZE]);
+        cookie
+    }
+}
+
+impl Default for AddressValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}